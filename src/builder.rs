@@ -0,0 +1,385 @@
+use actix_web::http::header::{self, HeaderMap, HeaderName, HeaderValue};
+use actix_web::test::TestRequest;
+use actix_web::web::{Bytes, BytesMut};
+use std::collections::hash_map::RandomState;
+use std::fmt;
+use std::hash::{BuildHasher, Hasher};
+
+/// Line terminator written between the headers and bodies of the parts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LineEnding {
+    /// `\r\n`, as required by RFC 2046.
+    #[default]
+    CrLf,
+    /// `\n`, sent by some non-compliant clients.
+    Lf,
+}
+
+impl LineEnding {
+    fn as_str(&self) -> &'static str {
+        match self {
+            LineEnding::CrLf => "\r\n",
+            LineEnding::Lf => "\n",
+        }
+    }
+}
+
+/// A single part of a body built with `MultipartBuilder`.
+#[derive(Debug, Clone)]
+pub struct Part {
+    name: String,
+    filename: Option<String>,
+    content_type: Option<String>,
+    headers: Vec<(String, String)>,
+    data: Bytes,
+}
+
+impl Part {
+    pub fn text<N: Into<String>, V: Into<String>>(name: N, value: V) -> Self {
+        Part {
+            name: name.into(),
+            filename: None,
+            content_type: None,
+            headers: Vec::new(),
+            data: Bytes::from(value.into()),
+        }
+    }
+
+    pub fn file<N, F, M, D>(name: N, filename: F, mime: M, data: D) -> Self
+    where
+        N: Into<String>,
+        F: Into<String>,
+        M: Into<String>,
+        D: Into<Bytes>,
+    {
+        Part {
+            name: name.into(),
+            filename: Some(filename.into()),
+            content_type: Some(mime.into()),
+            headers: Vec::new(),
+            data: data.into(),
+        }
+    }
+
+    /// Adds a header to the part, written as-is after `Content-Disposition` and `Content-Type`.
+    /// A value with a line break is rejected when the body is written.
+    pub fn header<N: Into<String>, V: Into<String>>(mut self, name: N, value: V) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+}
+
+/// Why a `MultipartBuilder` can't write its body.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum BuildError {
+    /// The boundary isn't 1 to 70 of the characters allowed by RFC 2046.
+    Boundary(String),
+    /// A header of the part `part`, or its content type, has an invalid name or a value with
+    /// a line break, which would end the headers of the part.
+    Header { part: String, header: String },
+    /// The data of the named part contains `--` followed by the boundary, which would end it.
+    Delimiter(String),
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BuildError::Boundary(boundary) => write!(f, "Invalid boundary \"{}\"", boundary),
+            BuildError::Header { part, header } => {
+                write!(f, "Invalid {} header in part \"{}\"", header, part)
+            }
+            BuildError::Delimiter(part) => {
+                write!(f, "The data of part \"{}\" contains the boundary", part)
+            }
+        }
+    }
+}
+
+impl std::error::Error for BuildError {}
+
+/// Builds `multipart/form-data` bodies, for tests or for clients of a `Multipart<T>` route.
+///
+/// Names and filenames are escaped, while the boundary, header names and values, and the data
+/// of the parts, which must not contain the boundary, are checked when the body is written.
+///
+/// ```no_run
+/// # use actix_extract_multipart::MultipartBuilder;
+/// # let png_bytes = std::fs::read("fn.png").unwrap();
+/// let (body, headers) = MultipartBuilder::new()
+///     .text("string_param", "A simple test")
+///     .file("file_param", "fn.png", "image/png", png_bytes)
+///     .build()?;
+/// # Ok::<(), actix_extract_multipart::BuildError>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct MultipartBuilder {
+    boundary: String,
+    line_ending: LineEnding,
+    parts: Vec<Part>,
+}
+
+impl Default for MultipartBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MultipartBuilder {
+    pub fn new() -> Self {
+        MultipartBuilder {
+            boundary: random_boundary(),
+            line_ending: LineEnding::default(),
+            parts: Vec::new(),
+        }
+    }
+
+    pub fn boundary<B: Into<String>>(mut self, boundary: B) -> Self {
+        self.boundary = boundary.into();
+        self
+    }
+
+    pub fn line_ending(mut self, line_ending: LineEnding) -> Self {
+        self.line_ending = line_ending;
+        self
+    }
+
+    pub fn text<N: Into<String>, V: Into<String>>(self, name: N, value: V) -> Self {
+        self.part(Part::text(name, value))
+    }
+
+    pub fn file<N, F, M, D>(self, name: N, filename: F, mime: M, data: D) -> Self
+    where
+        N: Into<String>,
+        F: Into<String>,
+        M: Into<String>,
+        D: Into<Bytes>,
+    {
+        self.part(Part::file(name, filename, mime, data))
+    }
+
+    pub fn part(mut self, part: Part) -> Self {
+        self.parts.push(part);
+        self
+    }
+
+    pub fn get_boundary(&self) -> &str {
+        &self.boundary
+    }
+
+    /// Value of the request `Content-Type` header.
+    pub fn content_type(&self) -> String {
+        format!("multipart/form-data; boundary=\"{}\"", self.boundary)
+    }
+
+    pub fn body(&self) -> Result<Bytes, BuildError> {
+        let mut body = BytesMut::new();
+        for chunk in self.clone().into_chunks()? {
+            body.extend_from_slice(&chunk);
+        }
+        Ok(body.freeze())
+    }
+
+    /// The body split around the part data, so files are not copied into a single buffer. The
+    /// headers of each part are written when the iterator reaches it.
+    pub(crate) fn into_chunks(self) -> Result<impl Iterator<Item = Bytes>, BuildError> {
+        self.validate()?;
+
+        let eol = self.line_ending.as_str();
+        let after_parts = if self.parts.is_empty() { "" } else { eol };
+        let closing = Bytes::from(format!("{}--{}--{}", after_parts, self.boundary, eol));
+        let boundary = self.boundary;
+        let chunks = self
+            .parts
+            .into_iter()
+            .enumerate()
+            .flat_map(move |(index, part)| {
+                let mut head = String::new();
+                if index > 0 {
+                    head.push_str(eol);
+                }
+                head.push_str(&format!("--{}{}", boundary, eol));
+                head.push_str(&format!(
+                    "Content-Disposition: form-data; name=\"{}\"",
+                    escape_quoted(&part.name)
+                ));
+                if let Some(filename) = &part.filename {
+                    head.push_str(&format!("; filename=\"{}\"", escape_quoted(filename)));
+                }
+                head.push_str(eol);
+                if let Some(content_type) = &part.content_type {
+                    head.push_str(&format!("Content-Type: {}{}", content_type, eol));
+                }
+                for (name, value) in &part.headers {
+                    head.push_str(&format!("{}: {}{}", name, value, eol));
+                }
+                head.push_str(eol);
+                [Bytes::from(head), part.data]
+            });
+        Ok(chunks.chain(std::iter::once(closing)))
+    }
+
+    fn validate(&self) -> Result<(), BuildError> {
+        if !valid_boundary(&self.boundary) {
+            return Err(BuildError::Boundary(self.boundary.clone()));
+        }
+        let delimiter = format!("--{}", self.boundary);
+        let finder = memchr::memmem::Finder::new(&delimiter);
+        for part in &self.parts {
+            if finder.find(&part.data).is_some() {
+                return Err(BuildError::Delimiter(part.name.clone()));
+            }
+            let content_type = part
+                .content_type
+                .as_ref()
+                .map(|content_type| ("Content-Type", content_type));
+            let headers = part
+                .headers
+                .iter()
+                .map(|(name, value)| (name.as_str(), value));
+            for (name, value) in content_type.into_iter().chain(headers) {
+                if HeaderName::from_bytes(name.as_bytes()).is_err()
+                    || value.contains(['\r', '\n', '\0'])
+                {
+                    return Err(BuildError::Header {
+                        part: part.name.clone(),
+                        header: name.to_owned(),
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the body and the request headers to send it with.
+    pub fn build(&self) -> Result<(Bytes, HeaderMap), BuildError> {
+        let body = self.body()?;
+        let content_type = HeaderValue::from_str(&self.content_type())
+            .map_err(|_| BuildError::Boundary(self.boundary.clone()))?;
+        let mut headers = HeaderMap::new();
+        headers.insert(header::CONTENT_TYPE, content_type);
+        Ok((body, headers))
+    }
+
+    pub fn test_request(&self) -> Result<TestRequest, BuildError> {
+        Ok(TestRequest::post()
+            .insert_header((header::CONTENT_TYPE, self.content_type()))
+            .set_payload(self.body()?))
+    }
+}
+
+/// 1 to 70 characters among `bchars` of RFC 2046, not ending with a space.
+fn valid_boundary(boundary: &str) -> bool {
+    (1..=70).contains(&boundary.len())
+        && !boundary.ends_with(' ')
+        && boundary
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "'()+_,-./:=? ".contains(c))
+}
+
+/// Quotes are percent-encoded in `Content-Disposition` parameters, like browsers do.
+fn escape_quoted(value: &str) -> String {
+    value
+        .replace('"', "%22")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+fn random_boundary() -> String {
+    let state = RandomState::new();
+    let mut boundary = String::with_capacity(32);
+    for round in 0..2u64 {
+        let mut hasher = state.build_hasher();
+        hasher.write_u64(round);
+        boundary.push_str(&format!("{:016x}", hasher.finish()));
+    }
+    boundary
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_body_layout() {
+        let body = MultipartBuilder::new()
+            .boundary("abbc761f78ff4d7cb7573b5a23f96ef0")
+            .text("first_param", "A simple test")
+            .part(
+                Part::file("file_param", "f\"n.txt", "text/plain", "test")
+                    .header("Content-MD5", "x"),
+            )
+            .body()
+            .unwrap();
+
+        assert_eq!(
+            body,
+            Bytes::from(
+                "--abbc761f78ff4d7cb7573b5a23f96ef0\r\n\
+                 Content-Disposition: form-data; name=\"first_param\"\r\n\r\n\
+                 A simple test\r\n\
+                 --abbc761f78ff4d7cb7573b5a23f96ef0\r\n\
+                 Content-Disposition: form-data; name=\"file_param\"; filename=\"f%22n.txt\"\r\n\
+                 Content-Type: text/plain\r\n\
+                 Content-MD5: x\r\n\r\n\
+                 test\r\n\
+                 --abbc761f78ff4d7cb7573b5a23f96ef0--\r\n"
+            )
+        );
+    }
+
+    #[test]
+    fn test_lf_line_ending_and_random_boundary() {
+        let builder = MultipartBuilder::new()
+            .line_ending(LineEnding::Lf)
+            .text("a", "b");
+        let body = builder.body().unwrap();
+
+        assert!(!body.windows(2).any(|w| w == b"\r\n"));
+        assert_ne!(builder.boundary, MultipartBuilder::new().boundary);
+    }
+
+    #[test]
+    fn test_invalid_boundary_and_headers_rejected() {
+        for boundary in [
+            "",
+            "a\"b",
+            "a\r\nX-Injected: 1",
+            "trailing ",
+            &"b".repeat(71),
+        ] {
+            assert!(matches!(
+                MultipartBuilder::new().boundary(boundary).build(),
+                Err(BuildError::Boundary(_))
+            ));
+        }
+
+        let injected = [
+            Part::file("f", "fn.txt", "text/plain\r\nX-Injected: 1", "test"),
+            Part::text("t", "test").header("X-Note", "a\r\n\r\nbody"),
+            Part::text("t", "test").header("X-Note\r\nX-Injected", "a"),
+        ];
+        for part in injected {
+            assert!(matches!(
+                MultipartBuilder::new().part(part).body(),
+                Err(BuildError::Header { .. })
+            ));
+        }
+
+        for data in ["a\r\n--b0\r\nb", "--b0--"] {
+            assert!(matches!(
+                MultipartBuilder::new().boundary("b0").text("t", data).body(),
+                Err(BuildError::Delimiter(part)) if part == "t"
+            ));
+        }
+
+        let body = MultipartBuilder::new()
+            .boundary("b0")
+            .file("f\r\n", "a\r\nb.txt", "text/plain", "test")
+            .body()
+            .unwrap();
+        assert!(body.starts_with(
+            b"--b0\r\nContent-Disposition: form-data; name=\"f%0D%0A\"; filename=\"a%0D%0Ab.txt\"\r\n"
+        ));
+    }
+}
@@ -0,0 +1,239 @@
+use crate::reader::{essence, parameter, MultipartReader, ReadError};
+use actix_web::error::PayloadError;
+use actix_web::http::header::{self, ContentRange, ContentRangeSpec};
+use actix_web::web::{Bytes, BytesMut};
+use futures::{Stream, StreamExt};
+use std::fmt;
+use std::io;
+
+/// Errors returned by `parse_byteranges`.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum ByteRangesError {
+    /// The content type isn't `multipart/byteranges` with a boundary.
+    ContentType,
+    /// The body couldn't be read, or isn't a complete multipart body.
+    Read(String),
+    /// The `Content-Range` of a part (numbered from 0) is missing or doesn't match its body.
+    InvalidRange { part: usize, reason: String },
+    /// The data of the ranges is larger in total than the limit passed to `parse_byteranges`.
+    TooLarge(usize),
+}
+
+impl fmt::Display for ByteRangesError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ByteRangesError::ContentType => {
+                write!(f, "The content type isn't multipart/byteranges")
+            }
+            ByteRangesError::Read(err) => write!(f, "{}", err),
+            ByteRangesError::InvalidRange { part, reason } => {
+                write!(f, "Invalid range in part #{}: {}", part, reason)
+            }
+            ByteRangesError::TooLarge(limit) => {
+                write!(f, "The ranges are larger than {} bytes", limit)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ByteRangesError {}
+
+impl From<ReadError> for ByteRangesError {
+    fn from(err: ReadError) -> Self {
+        ByteRangesError::Read(err.to_string())
+    }
+}
+
+/// Parses a `multipart/byteranges` body, such as the response to a multi-range `GET`, into
+/// its ranges and their data.
+///
+/// `content_type` is the `Content-Type` of the body, and `stream` either an actix `Payload`
+/// or the body stream of an HTTP client. Every part must have a satisfied `bytes`
+/// `Content-Range` whose length matches the part, within the complete length of the
+/// representation, which must be the same in every part. `limit` is the maximum size of the
+/// data of all the ranges, which are kept in memory: a body announcing more is rejected before
+/// its data is read.
+///
+/// ```no_run
+/// # use actix_extract_multipart::{parse_byteranges, ByteRangesError};
+/// # use actix_web::web::Bytes;
+/// # use futures::Stream;
+/// # async fn example(
+/// #     content_type: String,
+/// #     body: impl Stream<Item = Result<Bytes, std::io::Error>> + 'static,
+/// # ) -> Result<(), ByteRangesError> {
+/// for (range, data) in parse_byteranges(&content_type, body, 16 * 1024 * 1024).await? {
+///     // ...
+/// #   let _ = (range, data);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub async fn parse_byteranges<S, E>(
+    content_type: &str,
+    stream: S,
+    limit: usize,
+) -> Result<Vec<(ContentRange, Bytes)>, ByteRangesError>
+where
+    S: Stream<Item = Result<Bytes, E>> + 'static,
+    E: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    if essence(content_type) != "multipart/byteranges" {
+        return Err(ByteRangesError::ContentType);
+    }
+    let boundary = parameter(content_type, "boundary").ok_or(ByteRangesError::ContentType)?;
+    let stream = stream.map(|chunk| chunk.map_err(|err| PayloadError::Io(io::Error::other(err))));
+    let mut reader = MultipartReader::from_boundary(&boundary, stream);
+
+    let mut ranges = Vec::new();
+    let mut complete_length = None;
+    let mut remaining = limit as u64;
+    while let Some(head) = reader.next_part().await? {
+        let part = ranges.len();
+        let invalid = |reason: &str| ByteRangesError::InvalidRange {
+            part,
+            reason: reason.to_owned(),
+        };
+
+        let spec = head
+            .headers()
+            .get(header::CONTENT_RANGE)
+            .ok_or_else(|| invalid("missing Content-Range"))?
+            .to_str()
+            .ok()
+            .and_then(|value| value.parse::<ContentRangeSpec>().ok())
+            .ok_or_else(|| invalid("malformed Content-Range"))?;
+        let (first, last, length) = match &spec {
+            ContentRangeSpec::Bytes {
+                range: Some((first, last)),
+                instance_length,
+            } => (*first, *last, *instance_length),
+            ContentRangeSpec::Bytes { range: None, .. } => {
+                return Err(invalid("unsatisfied range"))
+            }
+            ContentRangeSpec::Unregistered { .. } => return Err(invalid("unit isn't bytes")),
+        };
+        let range_length = (last - first)
+            .checked_add(1)
+            .ok_or_else(|| invalid("range too large"))?;
+        if let Some(length) = length {
+            if last >= length {
+                return Err(invalid("range beyond the complete length"));
+            }
+            if *complete_length.get_or_insert(length) != length {
+                return Err(invalid("complete length differs from previous parts"));
+            }
+        }
+
+        remaining = remaining
+            .checked_sub(range_length)
+            .ok_or(ByteRangesError::TooLarge(limit))?;
+
+        let mut data = BytesMut::new();
+        while let Some(chunk) = reader.chunk().await? {
+            data.extend_from_slice(&chunk);
+            if data.len() as u64 > range_length {
+                return Err(invalid("part length doesn't match the range"));
+            }
+        }
+        if data.len() as u64 != range_length {
+            return Err(invalid("part length doesn't match the range"));
+        }
+
+        ranges.push((ContentRange(spec), data.freeze()));
+    }
+
+    Ok(ranges)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONTENT_TYPE: &str = "multipart/byteranges; boundary=THIS_STRING_SEPARATES";
+
+    fn body(second_range: &str) -> Vec<Result<Bytes, io::Error>> {
+        let body = format!(
+            "--THIS_STRING_SEPARATES\r\n\
+             Content-Type: text/plain\r\n\
+             Content-Range: bytes 0-4/20\r\n\r\n\
+             Hello\r\n\
+             --THIS_STRING_SEPARATES\r\n\
+             Content-Type: text/plain\r\n\
+             Content-Range: {}\r\n\r\n\
+             world\r\n\
+             --THIS_STRING_SEPARATES--\r\n",
+            second_range
+        );
+        body.into_bytes()
+            .chunks(9)
+            .map(|chunk| Ok(Bytes::copy_from_slice(chunk)))
+            .collect()
+    }
+
+    #[actix_rt::test]
+    async fn test_parse_byteranges() {
+        let stream = futures::stream::iter(body("bytes 15-19/20"));
+        let ranges = parse_byteranges(CONTENT_TYPE, stream, 100).await.unwrap();
+
+        assert_eq!(ranges.len(), 2);
+        assert_eq!(ranges[0].1, "Hello");
+        assert_eq!(
+            ranges[1].0,
+            ContentRange(ContentRangeSpec::Bytes {
+                range: Some((15, 19)),
+                instance_length: Some(20)
+            })
+        );
+
+        let body: Vec<u8> = body("bytes 15-19/20")
+            .into_iter()
+            .flat_map(|chunk| chunk.unwrap())
+            .collect();
+        let (_, payload) = actix_web::test::TestRequest::default()
+            .set_payload(body)
+            .to_http_parts();
+        let ranges = parse_byteranges(CONTENT_TYPE, payload, 100).await.unwrap();
+        assert_eq!(ranges[1].1, "world");
+    }
+
+    #[actix_rt::test]
+    async fn test_invalid_ranges() {
+        for (range, reason) in [
+            ("bytes 15-18/20", "part length doesn't match the range"),
+            (
+                "bytes 17-21/22",
+                "complete length differs from previous parts",
+            ),
+            ("bytes 15-19/19", "range beyond the complete length"),
+            ("bytes */20", "unsatisfied range"),
+            ("items 15-19/20", "unit isn't bytes"),
+            ("bytes 19-15/20", "malformed Content-Range"),
+            ("bytes 0-18446744073709551615/*", "range too large"),
+        ] {
+            let stream = futures::stream::iter(body(range));
+            match parse_byteranges(CONTENT_TYPE, stream, 100).await {
+                Err(ByteRangesError::InvalidRange {
+                    part: 1,
+                    reason: err,
+                }) => {
+                    assert_eq!(err, reason)
+                }
+                _ => panic!("{} should have been rejected", range),
+            }
+        }
+
+        let stream = futures::stream::iter(body("bytes 15-19/20"));
+        assert!(matches!(
+            parse_byteranges(CONTENT_TYPE, stream, 9).await,
+            Err(ByteRangesError::TooLarge(9))
+        ));
+
+        let stream = futures::stream::iter(body("bytes 15-19/20"));
+        assert!(matches!(
+            parse_byteranges("multipart/mixed; boundary=x", stream, 100).await,
+            Err(ByteRangesError::ContentType)
+        ));
+    }
+}
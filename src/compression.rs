@@ -0,0 +1,345 @@
+use actix_web::http::header::HeaderMap;
+#[cfg(any(feature = "gzip", feature = "brotli", feature = "zstd"))]
+use std::io::{self, Write};
+
+/// Only `identity` is accepted without a compression feature, which never fails.
+#[cfg_attr(
+    not(any(feature = "gzip", feature = "brotli", feature = "zstd")),
+    allow(dead_code)
+)]
+pub(crate) enum DecompressError {
+    Invalid,
+    TooLarge,
+}
+
+/// Output of the decompressors. Writes fail once more than `limit` bytes were produced,
+/// which stops decompression before a small part can expand into a huge one.
+#[cfg(any(feature = "gzip", feature = "brotli", feature = "zstd"))]
+struct LimitedWriter {
+    buffer: Vec<u8>,
+    written: usize,
+    limit: usize,
+    exceeded: bool,
+}
+
+#[cfg(any(feature = "gzip", feature = "brotli", feature = "zstd"))]
+impl LimitedWriter {
+    fn new(limit: usize) -> Self {
+        LimitedWriter {
+            buffer: Vec::new(),
+            written: 0,
+            limit,
+            exceeded: false,
+        }
+    }
+
+    /// Moves the decompressed bytes to `out`, and turns the result of a write into the error.
+    fn drain(&mut self, result: io::Result<()>, out: &mut Vec<u8>) -> Result<(), DecompressError> {
+        out.append(&mut self.buffer);
+        match result {
+            Ok(()) => Ok(()),
+            Err(_) if self.exceeded => Err(DecompressError::TooLarge),
+            Err(_) => Err(DecompressError::Invalid),
+        }
+    }
+}
+
+#[cfg(any(feature = "gzip", feature = "brotli", feature = "zstd"))]
+impl io::Write for LimitedWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.written + buf.len() > self.limit {
+            self.exceeded = true;
+            return Err(io::Error::other("decompressed size limit exceeded"));
+        }
+        self.written += buf.len();
+        self.buffer.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Largest zstd window accepted, 8 MiB as RFC 9659 requires for `Content-Encoding: zstd`.
+/// zstd would otherwise allocate up to 128 MiB for a frame that asks for it.
+#[cfg(feature = "zstd")]
+const ZSTD_WINDOW_LOG_MAX: u32 = 23;
+
+enum Decompressor {
+    Identity,
+    #[cfg(feature = "gzip")]
+    Gzip(flate2::write::GzDecoder<LimitedWriter>),
+    #[cfg(feature = "gzip")]
+    Deflate(flate2::write::ZlibDecoder<LimitedWriter>),
+    #[cfg(feature = "brotli")]
+    Brotli(Box<brotli::DecompressorWriter<LimitedWriter>>),
+    /// The raw writer rather than `zstd::stream::write::Decoder`, whose `flush` accepts a
+    /// truncated frame: `finish` fails unless the last frame was complete.
+    #[cfg(feature = "zstd")]
+    Zstd(zstd::stream::zio::Writer<LimitedWriter, zstd::stream::raw::Decoder<'static>>),
+}
+
+/// Streaming decoder for the `Content-Encoding` of a part. Each algorithm is enabled by the
+/// feature of the same name (`gzip` also covers `deflate`).
+pub(crate) struct ContentDecoder {
+    decompressor: Decompressor,
+    name: String,
+}
+
+impl ContentDecoder {
+    /// Returns the unsupported encoding name as the error. `limit` is the maximum decompressed
+    /// size, unused without a compression feature.
+    #[cfg_attr(
+        not(any(feature = "gzip", feature = "brotli", feature = "zstd")),
+        allow(unused_variables)
+    )]
+    pub(crate) fn from_headers(headers: &HeaderMap, limit: usize) -> Result<Self, String> {
+        let name = match headers
+            .get("content-encoding")
+            .and_then(|value| value.to_str().ok())
+        {
+            Some(encoding) => encoding.trim().to_ascii_lowercase(),
+            None => "identity".to_owned(),
+        };
+
+        let decompressor = match name.as_str() {
+            "identity" => Decompressor::Identity,
+            #[cfg(feature = "gzip")]
+            "gzip" | "x-gzip" => {
+                Decompressor::Gzip(flate2::write::GzDecoder::new(LimitedWriter::new(limit)))
+            }
+            #[cfg(feature = "gzip")]
+            "deflate" => {
+                Decompressor::Deflate(flate2::write::ZlibDecoder::new(LimitedWriter::new(limit)))
+            }
+            #[cfg(feature = "brotli")]
+            "br" => Decompressor::Brotli(Box::new(brotli::DecompressorWriter::new(
+                LimitedWriter::new(limit),
+                4096,
+            ))),
+            #[cfg(feature = "zstd")]
+            "zstd" => {
+                let mut decoder = zstd::stream::raw::Decoder::new().map_err(|_| name.clone())?;
+                decoder
+                    .set_parameter(zstd::zstd_safe::DParameter::WindowLogMax(
+                        ZSTD_WINDOW_LOG_MAX,
+                    ))
+                    .map_err(|_| name.clone())?;
+                Decompressor::Zstd(zstd::stream::zio::Writer::new(
+                    LimitedWriter::new(limit),
+                    decoder,
+                ))
+            }
+            _ => return Err(name),
+        };
+
+        Ok(ContentDecoder { decompressor, name })
+    }
+
+    pub(crate) fn is_identity(&self) -> bool {
+        matches!(self.decompressor, Decompressor::Identity)
+    }
+
+    pub(crate) fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Appends the decompressed bytes of `chunk` to `out`.
+    pub(crate) fn decode(
+        &mut self,
+        chunk: &[u8],
+        out: &mut Vec<u8>,
+    ) -> Result<(), DecompressError> {
+        match &mut self.decompressor {
+            Decompressor::Identity => {
+                out.extend_from_slice(chunk);
+                Ok(())
+            }
+            #[cfg(feature = "gzip")]
+            Decompressor::Gzip(decoder) => {
+                let result = decoder.write_all(chunk);
+                decoder.get_mut().drain(result, out)
+            }
+            #[cfg(feature = "gzip")]
+            Decompressor::Deflate(decoder) => {
+                let result = decoder.write_all(chunk);
+                decoder.get_mut().drain(result, out)
+            }
+            #[cfg(feature = "brotli")]
+            Decompressor::Brotli(decoder) => {
+                let result = decoder.write_all(chunk);
+                decoder.get_mut().drain(result, out)
+            }
+            #[cfg(feature = "zstd")]
+            Decompressor::Zstd(decoder) => {
+                let result = decoder.write_all(chunk);
+                decoder.writer_mut().drain(result, out)
+            }
+        }
+    }
+
+    /// Flushes what is left once the part is complete, and checks that the stream was.
+    #[cfg_attr(
+        not(any(feature = "gzip", feature = "brotli", feature = "zstd")),
+        allow(unused_variables, clippy::ptr_arg)
+    )]
+    pub(crate) fn finish(self, out: &mut Vec<u8>) -> Result<(), DecompressError> {
+        match self.decompressor {
+            Decompressor::Identity => Ok(()),
+            #[cfg(feature = "gzip")]
+            Decompressor::Gzip(mut decoder) => {
+                let result = decoder.try_finish();
+                decoder.get_mut().drain(result, out)
+            }
+            #[cfg(feature = "gzip")]
+            Decompressor::Deflate(mut decoder) => {
+                let result = decoder.try_finish();
+                decoder.get_mut().drain(result, out)
+            }
+            #[cfg(feature = "brotli")]
+            Decompressor::Brotli(mut decoder) => {
+                let result = decoder.close();
+                decoder.get_mut().drain(result, out)
+            }
+            #[cfg(feature = "zstd")]
+            Decompressor::Zstd(mut decoder) => {
+                let result = decoder.finish();
+                decoder.writer_mut().drain(result, out)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::header::HeaderValue;
+
+    fn decoder(encoding: &str, limit: usize) -> Result<ContentDecoder, String> {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            actix_web::http::header::CONTENT_ENCODING,
+            HeaderValue::from_str(encoding).unwrap(),
+        );
+        ContentDecoder::from_headers(&headers, limit)
+    }
+
+    #[cfg(any(feature = "gzip", feature = "brotli", feature = "zstd"))]
+    fn decode_chunks(mut decoder: ContentDecoder, data: &[u8]) -> Result<Vec<u8>, DecompressError> {
+        let mut out = Vec::new();
+        for chunk in data.chunks(7) {
+            decoder.decode(chunk, &mut out)?;
+        }
+        decoder.finish(&mut out)?;
+        Ok(out)
+    }
+
+    #[test]
+    fn test_unsupported_encoding() {
+        assert!(decoder("identity", 10).unwrap().is_identity());
+        assert_eq!(decoder("compress", 10).err().unwrap(), "compress");
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn test_gzip_limit() {
+        let data = vec![b'a'; 100_000];
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::best());
+        encoder.write_all(&data).unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        assert_eq!(
+            decode_chunks(decoder("gzip", 100_000).unwrap(), &compressed).ok(),
+            Some(data)
+        );
+        assert!(matches!(
+            decode_chunks(decoder("gzip", 99_999).unwrap(), &compressed),
+            Err(DecompressError::TooLarge)
+        ));
+        assert!(matches!(
+            decode_chunks(decoder("gzip", 100_000).unwrap(), &compressed[..20]),
+            Err(DecompressError::Invalid)
+        ));
+    }
+
+    #[cfg(feature = "brotli")]
+    #[test]
+    fn test_brotli() {
+        let mut compressed = Vec::new();
+        {
+            let mut encoder = brotli::CompressorWriter::new(&mut compressed, 4096, 5, 22);
+            encoder.write_all(b"test data").unwrap();
+        }
+
+        assert_eq!(
+            decode_chunks(decoder("br", 100).unwrap(), &compressed).ok(),
+            Some(b"test data".to_vec())
+        );
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn test_zstd_limit() {
+        let compressed = zstd::encode_all(&vec![0u8; 10_000][..], 3).unwrap();
+
+        assert_eq!(
+            decode_chunks(decoder("zstd", 10_000).unwrap(), &compressed)
+                .map(|data| data.len())
+                .ok(),
+            Some(10_000)
+        );
+        assert!(matches!(
+            decode_chunks(decoder("zstd", 100).unwrap(), &compressed),
+            Err(DecompressError::TooLarge)
+        ));
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn test_zstd_window_limit() {
+        let compress = |window_log| {
+            let mut encoder = zstd::stream::write::Encoder::new(Vec::new(), 3).unwrap();
+            encoder
+                .set_parameter(zstd::zstd_safe::CParameter::WindowLog(window_log))
+                .unwrap();
+            encoder.write_all(&[b'a'; 1000]).unwrap();
+            encoder.finish().unwrap()
+        };
+
+        assert_eq!(
+            decode_chunks(
+                decoder("zstd", 1000).unwrap(),
+                &compress(ZSTD_WINDOW_LOG_MAX)
+            )
+            .map(|data| data.len())
+            .ok(),
+            Some(1000)
+        );
+        assert!(matches!(
+            decode_chunks(decoder("zstd", 1000).unwrap(), &compress(27)),
+            Err(DecompressError::Invalid)
+        ));
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn test_zstd_truncated() {
+        let compressed =
+            zstd::encode_all(&b"test data, long enough to be a few blocks"[..], 3).unwrap();
+
+        let concatenated = [compressed.clone(), compressed.clone()].concat();
+        assert_eq!(
+            decode_chunks(decoder("zstd", 100).unwrap(), &concatenated)
+                .map(|data| data.len())
+                .ok(),
+            Some(82)
+        );
+        for len in [compressed.len() - 1, compressed.len() / 2, 4] {
+            assert!(matches!(
+                decode_chunks(decoder("zstd", 100).unwrap(), &compressed[..len]),
+                Err(DecompressError::Invalid)
+            ));
+        }
+    }
+}
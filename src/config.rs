@@ -0,0 +1,208 @@
+use crate::de::{Delimiter, Delimiters};
+use crate::digest::Algorithm;
+use crate::format::{FormatError, Formats};
+use crate::params::Precedence;
+use actix_web::{web, HttpRequest};
+use serde_json::Value;
+
+/// Settings used by the `Multipart<T>` extractor.
+///
+/// Register it with `App::app_data` (or wrapped in `web::Data`); requests fall back to
+/// the default configuration otherwise.
+#[derive(Clone, Debug)]
+pub struct MultipartConfig {
+    digests: Vec<Algorithm>,
+    keep_raw: bool,
+    decompressed_limit: usize,
+    max_nesting: usize,
+    nested_limit: usize,
+    formats: Formats,
+    merge_path: bool,
+    merge_query: bool,
+    precedence: Precedence,
+    strict: bool,
+    positions: bool,
+    delimiters: Delimiters,
+}
+
+impl Default for MultipartConfig {
+    fn default() -> Self {
+        MultipartConfig {
+            digests: Vec::new(),
+            keep_raw: false,
+            decompressed_limit: 32 * 1024 * 1024,
+            max_nesting: 1,
+            nested_limit: 32 * 1024 * 1024,
+            formats: Formats::default(),
+            merge_path: false,
+            merge_query: false,
+            precedence: Precedence::default(),
+            strict: false,
+            positions: false,
+            delimiters: Delimiters::default(),
+        }
+    }
+}
+
+impl MultipartConfig {
+    /// Computes the given digest for every received file, see `File::digest`.
+    pub fn digest(mut self, algorithm: Algorithm) -> Self {
+        if !self.digests.contains(&algorithm) {
+            self.digests.push(algorithm);
+        }
+        self
+    }
+
+    /// Keeps the bytes of file parts as they were sent, before the `Content-Transfer-Encoding`
+    /// and `Content-Encoding` were decoded, see `File::raw_data`.
+    pub fn keep_raw(mut self, keep_raw: bool) -> Self {
+        self.keep_raw = keep_raw;
+        self
+    }
+
+    /// Maximum size of a part once its `Content-Encoding` is decompressed, 32 MiB by default.
+    pub fn decompressed_limit(mut self, limit: usize) -> Self {
+        self.decompressed_limit = limit;
+        self
+    }
+
+    /// How deep `multipart/mixed` parts can be nested in a field, 1 by default. Requests with
+    /// deeper parts are rejected, and 0 rejects nested parts altogether.
+    pub fn max_nesting(mut self, depth: usize) -> Self {
+        self.max_nesting = depth;
+        self
+    }
+
+    /// Maximum size of a nested `multipart/mixed` part, and so of the files inside it, 32 MiB
+    /// by default. The nested body is read in memory before its parts are parsed.
+    pub fn nested_limit(mut self, limit: usize) -> Self {
+        self.nested_limit = limit;
+        self
+    }
+
+    /// Registers the decoder of a structured part format, replacing the built-in one for the
+    /// same content type. Text parts of that type are decoded into the nested field:
+    ///
+    /// ```no_run
+    /// # use actix_extract_multipart::MultipartConfig;
+    /// # use serde_json::Value;
+    /// # let config =
+    /// MultipartConfig::default().format("application/x-ndjson", |data| {
+    ///     let lines = data.split(|byte| *byte == b'\n').filter(|line| !line.is_empty());
+    ///     Ok(Value::Array(lines.map(serde_json::from_slice).collect::<Result<_, _>>()?))
+    /// })
+    /// # ;
+    /// ```
+    pub fn format<F>(mut self, content_type: &str, decoder: F) -> Self
+    where
+        F: Fn(&[u8]) -> Result<Value, FormatError> + Send + Sync + 'static,
+    {
+        self.formats.insert(content_type, decoder);
+        self
+    }
+
+    /// Adds the path parameters of the route (`/{project}/upload`) to the fields of the body.
+    pub fn merge_path(mut self, merge: bool) -> Self {
+        self.merge_path = merge;
+        self
+    }
+
+    /// Adds the query parameters (`?folder_id=...`) to the fields of the body. Path parameters
+    /// take precedence over query parameters of the same name.
+    pub fn merge_query(mut self, merge: bool) -> Self {
+        self.merge_query = merge;
+        self
+    }
+
+    /// Which value is kept when a merged parameter is also sent in the body, or in both the
+    /// path and the query. Such requests are rejected by default.
+    pub fn precedence(mut self, precedence: Precedence) -> Self {
+        self.precedence = precedence;
+        self
+    }
+
+    /// Rejects requests with fields that the target structure doesn't declare, listing their
+    /// names in `MultipartError::UnknownFields`. A `#[serde(flatten)]` catch-all accepts them all.
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Wraps errors about a field in `MultipartError::AtPart`, with the index and byte offset
+    /// of the part it was read from.
+    pub fn positions(mut self, positions: bool) -> Self {
+        self.positions = positions;
+        self
+    }
+
+    /// Splits a text field into the items of a list field on `delimiter`, for clients sending
+    /// `tags=a,b,c` instead of repeated `tags[]` parts. Each item is read as the item type of
+    /// the list, and lists sent as repeated parts are unchanged.
+    pub fn list_delimiter(mut self, delimiter: Delimiter) -> Self {
+        self.delimiters.all = Some(delimiter);
+        self
+    }
+
+    /// Like `list_delimiter`, for a single field. It takes precedence over the global delimiter.
+    pub fn field_delimiter(mut self, field: &str, delimiter: Delimiter) -> Self {
+        self.delimiters
+            .fields
+            .insert(field.replace("[]", ""), delimiter);
+        self
+    }
+
+    pub(crate) fn digests(&self) -> &[Algorithm] {
+        &self.digests
+    }
+
+    pub(crate) fn keeps_raw(&self) -> bool {
+        self.keep_raw
+    }
+
+    pub(crate) fn get_decompressed_limit(&self) -> usize {
+        self.decompressed_limit
+    }
+
+    pub(crate) fn get_max_nesting(&self) -> usize {
+        self.max_nesting
+    }
+
+    pub(crate) fn get_nested_limit(&self) -> usize {
+        self.nested_limit
+    }
+
+    pub(crate) fn formats(&self) -> &Formats {
+        &self.formats
+    }
+
+    pub(crate) fn merges_path(&self) -> bool {
+        self.merge_path
+    }
+
+    pub(crate) fn merges_query(&self) -> bool {
+        self.merge_query
+    }
+
+    pub(crate) fn get_precedence(&self) -> Precedence {
+        self.precedence
+    }
+
+    pub(crate) fn is_strict(&self) -> bool {
+        self.strict
+    }
+
+    pub(crate) fn keeps_positions(&self) -> bool {
+        self.positions
+    }
+
+    pub(crate) fn delimiters(&self) -> &Delimiters {
+        &self.delimiters
+    }
+
+    pub(crate) fn from_req(req: &HttpRequest) -> Self {
+        req.app_data::<Self>()
+            .or_else(|| req.app_data::<web::Data<Self>>().map(|d| d.as_ref()))
+            .cloned()
+            .unwrap_or_default()
+    }
+}
@@ -0,0 +1,610 @@
+use crate::File;
+use serde::de::value::MapAccessDeserializer;
+use serde::de::{
+    self, DeserializeOwned, DeserializeSeed, Deserializer, Error, IntoDeserializer, MapAccess,
+    SeqAccess, Visitor,
+};
+use serde::forward_to_deserialize_any;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::ops::Deref;
+
+/// Name `CsvFile` is deserialized with, as a tuple struct whose length is `MAX_ROWS`, and key
+/// marking the records of a file parsed while it was received.
+pub(crate) const CSV_TOKEN: &str = "$actix_extract_multipart::CsvFile";
+
+/// A CSV file part read into rows of `R`, with a header line naming the columns. When `R` is a
+/// structure, the header line must name at least one of its fields, and the columns of fields
+/// without a default; other columns are ignored.
+///
+/// The file is parsed while the part is received, and rejected at the first row over
+/// `MAX_ROWS`: only its records are kept in memory, never the file itself. This holds for the
+/// fields of the extracted structure, directly or in an `Option` or a `name[]` list; elsewhere,
+/// e.g. inside a `#[serde(flatten)]` structure or with `MultipartMap`, the file is parsed
+/// once it has been received.
+///
+/// ```no_run
+/// # use actix_extract_multipart::CsvFile;
+/// # use serde::Deserialize;
+/// # #[derive(Deserialize)]
+/// # struct User {}
+/// #[derive(Deserialize)]
+/// struct Import {
+///     dry_run: bool,
+///     users: CsvFile<User, 10_000>,
+/// }
+/// ```
+///
+/// Errors name the line of the file, e.g. `users: line 7: field 2: invalid digit found in string`.
+#[derive(Debug)]
+pub struct CsvFile<R, const MAX_ROWS: usize = { usize::MAX }> {
+    name: String,
+    rows: Vec<R>,
+}
+
+impl<R, const MAX_ROWS: usize> CsvFile<R, MAX_ROWS> {
+    /// The name of the uploaded file.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn rows(&self) -> &[R] {
+        &self.rows
+    }
+
+    pub fn into_rows(self) -> Vec<R> {
+        self.rows
+    }
+}
+
+impl<R, const MAX_ROWS: usize> Deref for CsvFile<R, MAX_ROWS> {
+    type Target = [R];
+
+    fn deref(&self) -> &[R] {
+        &self.rows
+    }
+}
+
+/// Parses a CSV file chunk by chunk into its records, the header line first, checking the
+/// number of fields and of rows as they arrive.
+pub(crate) struct CsvRecords {
+    reader: csv_core::Reader,
+    max_rows: usize,
+    /// The field being read, up to `len`.
+    field: Vec<u8>,
+    len: usize,
+    record: Vec<String>,
+    lines: Vec<u64>,
+    records: Vec<Vec<String>>,
+    received: bool,
+}
+
+impl CsvRecords {
+    pub(crate) fn new(max_rows: usize) -> Self {
+        CsvRecords {
+            reader: csv_core::Reader::new(),
+            max_rows,
+            field: vec![0; 1024],
+            len: 0,
+            record: Vec::new(),
+            lines: Vec::new(),
+            records: Vec::new(),
+            received: false,
+        }
+    }
+
+    /// Whether no data was written.
+    pub(crate) fn is_empty(&self) -> bool {
+        !self.received
+    }
+
+    pub(crate) fn write(&mut self, mut data: &[u8]) -> Result<(), String> {
+        self.received |= !data.is_empty();
+        while !data.is_empty() {
+            let (read, _) = self.read(data)?;
+            data = &data[read..];
+        }
+        Ok(())
+    }
+
+    /// Reads the last record, which may not end with a line break.
+    pub(crate) fn finish(&mut self) -> Result<(), String> {
+        while !self.read(&[])?.1 {}
+        Ok(())
+    }
+
+    /// Reads a field from `input`, an empty input being the end of the file. Returns the
+    /// number of bytes read, and whether more input is needed.
+    fn read(&mut self, input: &[u8]) -> Result<(usize, bool), String> {
+        let (result, read, written) = self.reader.read_field(input, &mut self.field[self.len..]);
+        self.len += written;
+        match result {
+            csv_core::ReadFieldResult::InputEmpty | csv_core::ReadFieldResult::End => {
+                return Ok((read, true))
+            }
+            csv_core::ReadFieldResult::OutputFull => {
+                let len = self.field.len();
+                self.field.resize(len * 2, 0);
+            }
+            csv_core::ReadFieldResult::Field { record_end } => {
+                let field = std::str::from_utf8(&self.field[..self.len]).map_err(|_| {
+                    format!("line {}: the field isn't valid UTF-8", self.reader.line())
+                })?;
+                self.record.push(field.to_owned());
+                self.len = 0;
+                if record_end {
+                    // A record ending with `\n` has its line break counted, but not one ending
+                    // with `\r\n`, whose `\n` is read with the next record.
+                    let end = self.reader.line() - u64::from(read > 0 && input[read - 1] == b'\n');
+                    self.end_record(end)?;
+                }
+            }
+        }
+        Ok((read, false))
+    }
+
+    /// Ends the record read, whose last line is `end`.
+    fn end_record(&mut self, end: u64) -> Result<(), String> {
+        let record = std::mem::take(&mut self.record);
+        let breaks = record
+            .iter()
+            .map(|field| field.matches('\n').count() as u64);
+        let line = end - breaks.sum::<u64>();
+        if let Some(headers) = self.records.first() {
+            if record.len() != headers.len() {
+                return Err(format!(
+                    "line {}: found a record with {} fields, but the header line has {}",
+                    line,
+                    record.len(),
+                    headers.len()
+                ));
+            }
+            if self.records.len() - 1 == self.max_rows {
+                return Err(format!("the file has more than {} rows", self.max_rows));
+            }
+        }
+        self.lines.push(line);
+        self.records.push(record);
+        Ok(())
+    }
+
+    /// The value of the field, which `CsvFile` reads back.
+    pub(crate) fn into_value(self, name: &str) -> Value {
+        json!({
+            CSV_TOKEN: true,
+            "name": name,
+            "lines": self.lines,
+            "records": self.records,
+        })
+    }
+}
+
+/// The records of a file, as read by `CsvRecords`.
+#[derive(Deserialize)]
+struct Parsed {
+    name: String,
+    lines: Vec<u64>,
+    records: Vec<Vec<String>>,
+}
+
+impl Parsed {
+    fn into_csv_file<R: DeserializeOwned, const MAX_ROWS: usize>(
+        self,
+    ) -> Result<CsvFile<R, MAX_ROWS>, String> {
+        if self.records.len() > MAX_ROWS.saturating_add(1) {
+            return Err(format!("the file has more than {} rows", MAX_ROWS));
+        }
+        let mut records = self.records.into_iter().zip(self.lines);
+        let headers = match records.next() {
+            Some((headers, _)) => csv::StringRecord::from(headers),
+            None => return Err("the header line is missing".to_owned()),
+        };
+        check_headers::<R>(&headers)?;
+
+        let rows = records
+            .map(|(record, line)| {
+                let mut record = csv::StringRecord::from(record);
+                let mut position = csv::Position::new();
+                position.set_line(line);
+                record.set_position(Some(position));
+                record.deserialize(Some(&headers)).map_err(row_error)
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(CsvFile {
+            name: self.name,
+            rows,
+        })
+    }
+}
+
+fn row_error(err: csv::Error) -> String {
+    match (err.kind(), err.position()) {
+        (
+            csv::ErrorKind::Deserialize {
+                pos: Some(pos),
+                err,
+            },
+            _,
+        ) => {
+            format!("line {}: {}", pos.line(), err)
+        }
+        (_, Some(pos)) => format!("line {}: {}", pos.line(), err),
+        (_, None) => err.to_string(),
+    }
+}
+
+/// Fields of `T` read as a `CsvFile`, directly or in an `Option` or a list, with their
+/// `MAX_ROWS`. Each field is probed like in `names_field`.
+pub(crate) fn csv_fields<'de, T: Deserialize<'de>>() -> HashMap<String, usize> {
+    let mut fields = None;
+    let _ = T::deserialize(FieldProbe::<RowsProbe> {
+        key: "",
+        value: None,
+        fields: &mut fields,
+    });
+
+    let mut csv_fields = HashMap::new();
+    for field in fields.unwrap_or_default() {
+        let mut max_rows = None;
+        let _ = T::deserialize(FieldProbe {
+            key: field,
+            value: Some(RowsProbe(&mut max_rows)),
+            fields: &mut None,
+        });
+        if let Some(max_rows) = max_rows {
+            csv_fields.insert((*field).to_owned(), max_rows);
+        }
+    }
+    csv_fields
+}
+
+/// Whether `header` names a field of `R`, or one of its aliases, or `None` when `R` isn't a
+/// structure. The `Deserialize` implementation of `R` is given a map with `header` as its only
+/// key: it reads the value of an unknown key with `deserialize_ignored_any`.
+fn names_field<'de, R: Deserialize<'de>>(header: &str) -> Option<bool> {
+    let mut known = false;
+    let mut fields = None;
+    let _ = R::deserialize(FieldProbe {
+        key: header,
+        value: Some(KnownProbe(&mut known)),
+        fields: &mut fields,
+    });
+    fields.map(|_| known)
+}
+
+/// Deserializer of a structure given a map with `key` as its only key, whose value is read
+/// with the `value` probe.
+struct FieldProbe<'a, P> {
+    key: &'a str,
+    value: Option<P>,
+    /// Set to the fields of the structure.
+    fields: &'a mut Option<&'static [&'static str]>,
+}
+
+impl<'de, P: Deserializer<'de, Error = de::value::Error>> Deserializer<'de> for FieldProbe<'_, P> {
+    type Error = de::value::Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, _: V) -> Result<V::Value, Self::Error> {
+        Err(de::value::Error::custom("not a structure"))
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        *self.fields = Some(fields);
+        visitor.visit_map(self)
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf
+        option unit unit_struct newtype_struct seq tuple tuple_struct map enum identifier
+        ignored_any
+    }
+}
+
+impl<'de, P: Deserializer<'de, Error = de::value::Error>> MapAccess<'de> for FieldProbe<'_, P> {
+    type Error = de::value::Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Self::Error> {
+        seed.deserialize(self.key.to_owned().into_deserializer())
+            .map(Some)
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(
+        &mut self,
+        seed: V,
+    ) -> Result<V::Value, Self::Error> {
+        match self.value.take() {
+            Some(value) => seed.deserialize(value),
+            None => Err(de::value::Error::custom("probed")),
+        }
+    }
+}
+
+/// Records whether the value of the probed key is read as a field.
+struct KnownProbe<'a>(&'a mut bool);
+
+impl<'de> Deserializer<'de> for KnownProbe<'_> {
+    type Error = de::value::Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, _: V) -> Result<V::Value, Self::Error> {
+        *self.0 = true;
+        Err(de::value::Error::custom("field found"))
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, _: V) -> Result<V::Value, Self::Error> {
+        Err(de::value::Error::custom("unknown field"))
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf
+        option unit unit_struct newtype_struct seq tuple tuple_struct map enum identifier
+        struct
+    }
+}
+
+/// Records the `MAX_ROWS` of a `CsvFile` value, looking into options and lists.
+struct RowsProbe<'a>(&'a mut Option<usize>);
+
+impl<'de> Deserializer<'de> for RowsProbe<'_> {
+    type Error = de::value::Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, _: V) -> Result<V::Value, Self::Error> {
+        Err(de::value::Error::custom("not a CSV file"))
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_seq(Element(Some(self)))
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        if name == CSV_TOKEN {
+            *self.0 = Some(len);
+        }
+        self.deserialize_any(visitor)
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf
+        unit unit_struct newtype_struct tuple map struct enum identifier ignored_any
+    }
+}
+
+/// A list with the probed value as its only element.
+struct Element<'a>(Option<RowsProbe<'a>>);
+
+impl<'de> SeqAccess<'de> for Element<'_> {
+    type Error = de::value::Error;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, Self::Error> {
+        self.0
+            .take()
+            .map(|probe| seed.deserialize(probe))
+            .transpose()
+    }
+}
+
+/// Checks that the header line names at least one field of `R`, which catches a missing header
+/// line, read as the names of the columns. Columns of fields without a default that are still
+/// missing are reported by the first row.
+fn check_headers<'de, R: Deserialize<'de>>(headers: &csv::StringRecord) -> Result<(), String> {
+    if headers.iter().all(str::is_empty) {
+        return Err("the header line is missing".to_owned());
+    }
+    let named: Vec<Option<bool>> = headers.iter().map(names_field::<R>).collect();
+    match named.iter().any(|known| *known != Some(false)) {
+        true => Ok(()),
+        false => Err("the header line doesn't name any field".to_owned()),
+    }
+}
+
+impl<'de, R: DeserializeOwned, const MAX_ROWS: usize> Deserialize<'de> for CsvFile<R, MAX_ROWS> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_tuple_struct(CSV_TOKEN, MAX_ROWS, CsvVisitor(PhantomData))
+    }
+}
+
+struct CsvVisitor<R, const MAX_ROWS: usize>(PhantomData<R>);
+
+impl<'de, R: DeserializeOwned, const MAX_ROWS: usize> Visitor<'de> for CsvVisitor<R, MAX_ROWS> {
+    type Value = CsvFile<R, MAX_ROWS>;
+
+    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        formatter.write_str("a CSV file")
+    }
+
+    /// The records of a file parsed while it was received.
+    fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+        Parsed::deserialize(MapAccessDeserializer::new(map))?
+            .into_csv_file()
+            .map_err(A::Error::custom)
+    }
+
+    /// A file that wasn't parsed while it was received.
+    fn visit_newtype_struct<D: Deserializer<'de>>(
+        self,
+        deserializer: D,
+    ) -> Result<Self::Value, D::Error> {
+        let file = File::deserialize(deserializer)?;
+        let mut records = CsvRecords::new(MAX_ROWS);
+        records
+            .write(file.data())
+            .and_then(|_| records.finish())
+            .map_err(D::Error::custom)?;
+        Parsed {
+            name: file.name,
+            lines: records.lines,
+            records: records.records,
+        }
+        .into_csv_file()
+        .map_err(D::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Multipart, MultipartBuilder};
+    use actix_web::FromRequest;
+
+    #[allow(dead_code)]
+    #[derive(Debug, Deserialize)]
+    struct User {
+        name: String,
+        age: u32,
+    }
+
+    #[allow(dead_code)]
+    #[derive(Deserialize)]
+    struct Import {
+        dry_run: bool,
+        users: CsvFile<User, 2>,
+    }
+
+    async fn extract(csv: &str) -> Result<Multipart<Import>, actix_web::Error> {
+        let (req, mut payload) = MultipartBuilder::new()
+            .text("dry_run", "true")
+            .file("users", "users.csv", "text/csv", csv.to_owned())
+            .test_request()
+            .unwrap()
+            .to_http_parts();
+        Multipart::<Import>::from_request(&req, &mut payload).await
+    }
+
+    #[actix_rt::test]
+    async fn test_csv_rows() {
+        let data = extract("name,age,email\r\nAda,36,\r\nAlan,41,\r\n")
+            .await
+            .unwrap();
+        assert_eq!(data.users.name(), "users.csv");
+        assert_eq!(data.users.len(), 2);
+        assert_eq!(data.users[1].name, "Alan");
+        assert_eq!(data.users[1].age, 41);
+
+        let err = extract("name,age\nAda,36\nAlan,forty\n")
+            .await
+            .err()
+            .unwrap();
+        assert!(err
+            .to_string()
+            .starts_with("Invalid value for field \"users\": line 3: field 1:"));
+
+        let err = extract("name,age\nAda,36\nAlan,41\nGrace,85\n")
+            .await
+            .err()
+            .unwrap();
+        assert!(err.to_string().ends_with("the file has more than 2 rows"));
+    }
+
+    #[actix_rt::test]
+    async fn test_csv_headers() {
+        let err = extract("Ada,36\nAlan,41\n").await.err().unwrap();
+        assert!(err
+            .to_string()
+            .ends_with("the header line doesn't name any field"));
+
+        let err = extract("name,years\nAda,36\n").await.err().unwrap();
+        assert!(err.to_string().ends_with("line 2: missing field `age`"));
+
+        let err = extract("\n").await.err().unwrap();
+        assert!(err.to_string().ends_with("the header line is missing"));
+    }
+
+    #[allow(dead_code)]
+    #[derive(Deserialize)]
+    struct Imports {
+        first: Option<CsvFile<User, 5>>,
+        #[serde(rename = "others")]
+        rest: Vec<CsvFile<User>>,
+        file: File,
+        count: u32,
+    }
+
+    #[test]
+    fn test_csv_fields_probed() {
+        let fields = csv_fields::<Imports>();
+
+        assert_eq!(fields.len(), 2);
+        assert_eq!(fields["first"], 5);
+        assert_eq!(fields["others"], usize::MAX);
+        assert!(csv_fields::<User>().is_empty());
+    }
+
+    #[test]
+    fn test_records_across_chunks() {
+        let data = b"name,age\r\n\"Ada\r\nLovelace\",36\r\n\r\nAlan,41";
+        for size in 1..data.len() {
+            let mut records = CsvRecords::new(2);
+            for chunk in data.chunks(size) {
+                records.write(chunk).unwrap();
+            }
+            records.finish().unwrap();
+
+            assert_eq!(records.lines, [1, 2, 5]);
+            assert_eq!(records.records[1], ["Ada\r\nLovelace", "36"]);
+            assert_eq!(records.records[2], ["Alan", "41"]);
+        }
+
+        let mut records = CsvRecords::new(1);
+        assert_eq!(
+            records.write(b"name,age\nAda,36\nAlan,41\n").err().unwrap(),
+            "the file has more than 1 rows"
+        );
+        let mut records = CsvRecords::new(1);
+        assert_eq!(
+            records.write(b"name,age\nAda\n").err().unwrap(),
+            "line 2: found a record with 1 fields, but the header line has 2"
+        );
+    }
+
+    #[allow(dead_code)]
+    #[derive(Deserialize)]
+    struct Contact {
+        #[serde(alias = "full_name")]
+        name: String,
+        email: Option<String>,
+        #[serde(default)]
+        phone: String,
+    }
+
+    #[actix_rt::test]
+    async fn test_csv_optional_and_aliased_columns() {
+        #[derive(Deserialize)]
+        struct Upload {
+            contacts: CsvFile<Contact>,
+        }
+
+        let (req, mut payload) = MultipartBuilder::new()
+            .file("contacts", "contacts.csv", "text/csv", "full_name\nAda\n")
+            .test_request()
+            .unwrap()
+            .to_http_parts();
+
+        let data = Multipart::<Upload>::from_request(&req, &mut payload)
+            .await
+            .unwrap();
+        assert_eq!(data.contacts[0].name, "Ada");
+        assert!(data.contacts[0].email.is_none());
+    }
+}
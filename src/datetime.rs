@@ -0,0 +1,314 @@
+//! Helpers to read date and time fields sent in a custom format. `chrono_format!` and
+//! `time_format!` declare a module to use with `#[serde(deserialize_with = "...")]`:
+//!
+//! ```no_run
+//! # #[cfg(feature = "chrono")]
+//! # mod example {
+//! # use chrono::NaiveDate;
+//! # use serde::Deserialize;
+//! actix_extract_multipart::chrono_format!(day_month_year, "%d/%m/%Y");
+//!
+//! #[derive(Deserialize)]
+//! struct Booking {
+//!     #[serde(deserialize_with = "day_month_year::deserialize")]
+//!     arrival: NaiveDate,
+//!     #[serde(default, deserialize_with = "day_month_year::deserialize_option")]
+//!     departure: Option<NaiveDate>,
+//! }
+//! # }
+//! ```
+
+use crate::de::{deserialize_optional_text, deserialize_text};
+use serde::de::{Deserializer, Error};
+
+/// Parses the text of a field, reporting the expected format on failure.
+fn parse<T, E: std::fmt::Display>(
+    text: &str,
+    format: &str,
+    parse: impl FnOnce(&str) -> Result<T, E>,
+) -> Result<T, String> {
+    parse(text.trim()).map_err(|err| format!("{} (expected format \"{}\")", err, format))
+}
+
+/// `chrono` types that can be parsed with a `strftime`-like format.
+#[cfg(feature = "chrono")]
+pub trait ChronoFormat: Sized {
+    /// Parses `text` with `format`, like the `parse_from_str` of the type.
+    fn parse_from_str(text: &str, format: &str) -> chrono::ParseResult<Self>;
+}
+
+#[cfg(feature = "chrono")]
+impl ChronoFormat for chrono::NaiveDate {
+    fn parse_from_str(text: &str, format: &str) -> chrono::ParseResult<Self> {
+        chrono::NaiveDate::parse_from_str(text, format)
+    }
+}
+
+#[cfg(feature = "chrono")]
+impl ChronoFormat for chrono::NaiveTime {
+    fn parse_from_str(text: &str, format: &str) -> chrono::ParseResult<Self> {
+        chrono::NaiveTime::parse_from_str(text, format)
+    }
+}
+
+#[cfg(feature = "chrono")]
+impl ChronoFormat for chrono::NaiveDateTime {
+    fn parse_from_str(text: &str, format: &str) -> chrono::ParseResult<Self> {
+        chrono::NaiveDateTime::parse_from_str(text, format)
+    }
+}
+
+#[cfg(feature = "chrono")]
+impl ChronoFormat for chrono::DateTime<chrono::FixedOffset> {
+    fn parse_from_str(text: &str, format: &str) -> chrono::ParseResult<Self> {
+        chrono::DateTime::parse_from_str(text, format)
+    }
+}
+
+/// Used by `chrono_format!`.
+#[doc(hidden)]
+#[cfg(feature = "chrono")]
+pub fn deserialize_chrono<'de, D, T>(deserializer: D, format: &str) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: ChronoFormat,
+{
+    let text = deserialize_text(deserializer)?;
+    parse(&text, format, |text| T::parse_from_str(text, format)).map_err(D::Error::custom)
+}
+
+/// Used by `chrono_format!`.
+#[doc(hidden)]
+#[cfg(feature = "chrono")]
+pub fn deserialize_chrono_option<'de, D, T>(
+    deserializer: D,
+    format: &str,
+) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: ChronoFormat,
+{
+    deserialize_optional_text(deserializer)?
+        .map(|text| parse(&text, format, |text| T::parse_from_str(text, format)))
+        .transpose()
+        .map_err(D::Error::custom)
+}
+
+/// `time` types that can be parsed with a format description, e.g. `[day]/[month]/[year]`.
+#[cfg(feature = "time")]
+pub trait TimeFormat: Sized {
+    /// Parses `text` with the items of a format description, like the `parse` of the type.
+    fn parse(
+        text: &str,
+        format: &[time::format_description::BorrowedFormatItem<'_>],
+    ) -> Result<Self, time::error::Parse>;
+}
+
+#[cfg(feature = "time")]
+macro_rules! time_format_impl {
+    ($($ty:ty),*) => {
+        $(
+            impl TimeFormat for $ty {
+                fn parse(
+                    text: &str,
+                    format: &[time::format_description::BorrowedFormatItem<'_>],
+                ) -> Result<Self, time::error::Parse> {
+                    <$ty>::parse(text, format)
+                }
+            }
+        )*
+    };
+}
+
+#[cfg(feature = "time")]
+time_format_impl!(
+    time::Date,
+    time::Time,
+    time::PrimitiveDateTime,
+    time::OffsetDateTime
+);
+
+/// A format description of `time_format!`, parsed once for every field using it.
+#[doc(hidden)]
+#[cfg(feature = "time")]
+pub struct TimeDescription {
+    format: &'static str,
+    items: Result<Vec<time::format_description::BorrowedFormatItem<'static>>, String>,
+}
+
+#[cfg(feature = "time")]
+impl TimeDescription {
+    pub fn new(format: &'static str) -> Self {
+        let items = time::format_description::parse_borrowed::<1>(format)
+            .map_err(|err| format!("invalid format \"{}\": {}", format, err));
+        TimeDescription { format, items }
+    }
+
+    fn parse<T: TimeFormat>(&self, text: &str) -> Result<T, String> {
+        let items = self.items.as_ref()?;
+        parse(text, self.format, |text| T::parse(text, items))
+    }
+}
+
+/// Used by `time_format!`.
+#[doc(hidden)]
+#[cfg(feature = "time")]
+pub fn deserialize_time<'de, D, T>(
+    deserializer: D,
+    description: &TimeDescription,
+) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: TimeFormat,
+{
+    let text = deserialize_text(deserializer)?;
+    description.parse(&text).map_err(D::Error::custom)
+}
+
+/// Used by `time_format!`.
+#[doc(hidden)]
+#[cfg(feature = "time")]
+pub fn deserialize_time_option<'de, D, T>(
+    deserializer: D,
+    description: &TimeDescription,
+) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: TimeFormat,
+{
+    deserialize_optional_text(deserializer)?
+        .map(|text| description.parse(&text))
+        .transpose()
+        .map_err(D::Error::custom)
+}
+
+/// Declares a module reading `chrono` fields with the given format, see `datetime`.
+#[cfg(feature = "chrono")]
+#[macro_export]
+macro_rules! chrono_format {
+    ($vis:vis $name:ident, $format:expr) => {
+        $vis mod $name {
+            pub fn deserialize<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+            where
+                D: $crate::__private::serde::Deserializer<'de>,
+                T: $crate::datetime::ChronoFormat,
+            {
+                $crate::datetime::deserialize_chrono(deserializer, $format)
+            }
+
+            pub fn deserialize_option<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+            where
+                D: $crate::__private::serde::Deserializer<'de>,
+                T: $crate::datetime::ChronoFormat,
+            {
+                $crate::datetime::deserialize_chrono_option(deserializer, $format)
+            }
+        }
+    };
+}
+
+/// Declares a module reading `time` fields with the given format description, see `datetime`.
+#[cfg(feature = "time")]
+#[macro_export]
+macro_rules! time_format {
+    ($vis:vis $name:ident, $format:expr) => {
+        $vis mod $name {
+            fn description() -> &'static $crate::datetime::TimeDescription {
+                static DESCRIPTION: ::std::sync::OnceLock<$crate::datetime::TimeDescription> =
+                    ::std::sync::OnceLock::new();
+                DESCRIPTION.get_or_init(|| $crate::datetime::TimeDescription::new($format))
+            }
+
+            pub fn deserialize<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+            where
+                D: $crate::__private::serde::Deserializer<'de>,
+                T: $crate::datetime::TimeFormat,
+            {
+                $crate::datetime::deserialize_time(deserializer, description())
+            }
+
+            pub fn deserialize_option<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+            where
+                D: $crate::__private::serde::Deserializer<'de>,
+                T: $crate::datetime::TimeFormat,
+            {
+                $crate::datetime::deserialize_time_option(deserializer, description())
+            }
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Multipart, MultipartBuilder};
+    use actix_web::FromRequest;
+    use serde::Deserialize;
+
+    #[cfg(feature = "chrono")]
+    #[actix_rt::test]
+    async fn test_chrono_format() {
+        crate::chrono_format!(day_month_year, "%d/%m/%Y");
+
+        #[allow(dead_code)]
+        #[derive(Deserialize)]
+        struct Booking {
+            #[serde(deserialize_with = "day_month_year::deserialize")]
+            arrival: chrono::NaiveDate,
+            #[serde(default, deserialize_with = "day_month_year::deserialize_option")]
+            departure: Option<chrono::NaiveDate>,
+        }
+
+        let (req, mut payload) = MultipartBuilder::new()
+            .text("arrival", "29/02/2024")
+            .test_request()
+            .unwrap()
+            .to_http_parts();
+        match Multipart::<Booking>::from_request(&req, &mut payload).await {
+            Ok(data) => {
+                assert_eq!(data.arrival.to_string(), "2024-02-29");
+                assert!(data.departure.is_none());
+            }
+            Err(_) => panic!("Failed to parse multipart into structure"),
+        }
+
+        let (req, mut payload) = MultipartBuilder::new()
+            .text("arrival", "2024-02-29")
+            .test_request()
+            .unwrap()
+            .to_http_parts();
+        let err = Multipart::<Booking>::from_request(&req, &mut payload)
+            .await
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("expected format \"%d/%m/%Y\""));
+    }
+
+    #[cfg(feature = "time")]
+    #[actix_rt::test]
+    async fn test_time_format() {
+        crate::time_format!(day_month_year, "[day]/[month]/[year]");
+
+        #[allow(dead_code)]
+        #[derive(Deserialize)]
+        struct Booking {
+            #[serde(deserialize_with = "day_month_year::deserialize")]
+            arrival: time::Date,
+            #[serde(default, deserialize_with = "day_month_year::deserialize_option")]
+            departure: Option<time::Date>,
+        }
+
+        let (req, mut payload) = MultipartBuilder::new()
+            .text("arrival", "29/02/2024")
+            .text("departure", "01/03/2024")
+            .test_request()
+            .unwrap()
+            .to_http_parts();
+        match Multipart::<Booking>::from_request(&req, &mut payload).await {
+            Ok(data) => {
+                assert_eq!(data.arrival.to_string(), "2024-02-29");
+                assert_eq!(data.departure.unwrap().to_string(), "2024-03-01");
+            }
+            Err(_) => panic!("Failed to parse multipart into structure"),
+        }
+    }
+}
@@ -0,0 +1,876 @@
+#[cfg(feature = "csv")]
+use crate::csv_file::CSV_TOKEN;
+use crate::file::{self, is_file_value, FILE_TOKEN};
+use crate::position::{PositionedFields, Positions, POSITIONED_TOKEN};
+use crate::{file_value, PartData};
+use serde::de::value::{MapAccessDeserializer, StringDeserializer};
+use serde::de::{
+    self, DeserializeSeed, IntoDeserializer, MapAccess, SeqAccess, Unexpected, Visitor,
+};
+use serde::forward_to_deserialize_any;
+use serde_json::{Error, Map, Value};
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+/// Deserializer of the collected fields.
+///
+/// Text parts are kept as sent and converted to the type the target asks for, so `"2024"` can
+/// be a `String` or a `u32`, and `"photo"` a unit variant. Types that accept anything, like
+/// internally tagged enums or flattened fields, see text as a number or a boolean when it's
+/// written exactly as one (`12`, `-3`, `1.5`, `true`), and as a string otherwise; use
+/// `deserialize_from_str` for their text fields.
+///
+/// Files and parts decoded by a format are deserialized as they are. Received files are handed
+/// over to `File` as a newtype, see `ReceivedFile`.
+pub(crate) struct FieldDeserializer<'a> {
+    value: Value,
+    mode: Mode,
+    delimiters: &'a Delimiters,
+    /// Whether text can be read as a `File`, i.e. the fields are parts of a multipart/form-data
+    /// body. Received files are read either way.
+    files: bool,
+    /// Splits text into a list when the target asks for one.
+    delimiter: Option<Delimiter>,
+    /// Positions of the parts the values were read from, for `Positioned` targets.
+    positions: &'a Positions,
+    /// Path of the value, e.g. `photos[1]`.
+    path: String,
+}
+
+/// Separator of the items of a list sent as a single text field, see
+/// `MultipartConfig::list_delimiter`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Delimiter {
+    /// `a,b, c`
+    Comma,
+    /// Any whitespace, including line breaks.
+    Whitespace,
+    /// One item per line.
+    Newline,
+    Char(char),
+}
+
+impl Delimiter {
+    /// The items of `text`, trimmed. Empty items are skipped.
+    fn split(self, text: &str) -> Vec<Value> {
+        let items: Box<dyn Iterator<Item = &str>> = match self {
+            Delimiter::Comma => Box::new(text.split(',')),
+            Delimiter::Whitespace => Box::new(text.split_whitespace()),
+            Delimiter::Newline => Box::new(text.lines()),
+            Delimiter::Char(delimiter) => Box::new(text.split(delimiter)),
+        };
+        items
+            .map(str::trim)
+            .filter(|item| !item.is_empty())
+            .map(|item| Value::String(item.to_owned()))
+            .collect()
+    }
+}
+
+/// The delimiters configured for all the fields, and for some by name.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Delimiters {
+    pub(crate) all: Option<Delimiter>,
+    pub(crate) fields: HashMap<String, Delimiter>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Mode {
+    /// The map of the collected fields.
+    Fields,
+    /// A field value, or an item of a `name[]` list.
+    Text,
+    /// A value inside a part decoded by a format, or inside a `multipart/related` root.
+    Value,
+}
+
+impl<'a> FieldDeserializer<'a> {
+    pub(crate) fn new(
+        value: Value,
+        delimiters: &'a Delimiters,
+        positions: &'a Positions,
+        files: bool,
+    ) -> Self {
+        FieldDeserializer {
+            value,
+            mode: Mode::Fields,
+            delimiters,
+            files,
+            delimiter: None,
+            positions,
+            path: String::new(),
+        }
+    }
+
+    /// The object of the value, read with `mode`.
+    fn fields(&self, fields: Map<String, Value>, mode: Mode) -> Fields<'a> {
+        Fields {
+            fields: fields.into_iter(),
+            value: None,
+            mode,
+            delimiters: self.delimiters,
+            files: self.files,
+            positions: self.positions,
+            path: self.path.clone(),
+        }
+    }
+
+    /// The items of the value, read with `mode`.
+    fn list(&self, values: Vec<Value>, mode: Mode) -> List<'a> {
+        List {
+            values: values.into_iter().enumerate(),
+            mode,
+            delimiters: self.delimiters,
+            files: self.files,
+            positions: self.positions,
+            path: self.path.clone(),
+        }
+    }
+
+    /// The text of a text value, or the deserializer back.
+    fn into_text(self) -> Result<String, Self> {
+        match self.value {
+            Value::String(text) if self.mode == Mode::Text => Ok(text),
+            value => Err(FieldDeserializer { value, ..self }),
+        }
+    }
+}
+
+fn unexpected(value: &Value) -> Unexpected<'_> {
+    match value {
+        Value::Null => Unexpected::Unit,
+        Value::Bool(value) => Unexpected::Bool(*value),
+        Value::Number(_) => Unexpected::Other("number"),
+        Value::String(text) => Unexpected::Str(text),
+        Value::Array(_) => Unexpected::Seq,
+        Value::Object(_) => Unexpected::Map,
+    }
+}
+
+/// The number or boolean `text` is the canonical form of, if any.
+fn guess(text: &str) -> Option<Value> {
+    let value = match text {
+        "true" => Value::Bool(true),
+        "false" => Value::Bool(false),
+        _ => match text.parse::<i64>() {
+            Ok(number) => Value::from(number),
+            Err(_) => match text.parse::<u64>() {
+                Ok(number) => Value::from(number),
+                Err(_) => Value::from(text.parse::<f64>().ok()?),
+            },
+        },
+    };
+    let canonical = value.to_string();
+    (canonical == text).then_some(value)
+}
+
+macro_rules! deserialize_parsed {
+    ($($method:ident => $visit:ident,)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+                match self.into_text() {
+                    Ok(text) => match text.trim().parse() {
+                        Ok(value) => visitor.$visit(value),
+                        Err(_) => Err(de::Error::invalid_value(Unexpected::Str(&text), &visitor)),
+                    },
+                    Err(deserializer) => deserializer.deserialize_any(visitor),
+                }
+            }
+        )*
+    };
+}
+
+impl<'de> de::Deserializer<'de> for FieldDeserializer<'_> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(mut self, visitor: V) -> Result<V::Value, Error> {
+        let value = std::mem::take(&mut self.value);
+        match (self.mode, value) {
+            (Mode::Fields, Value::Object(fields)) => {
+                visitor.visit_map(self.fields(fields, Mode::Text))
+            }
+            (_, value) if is_file_value(&value) => {
+                visitor.visit_newtype_struct(ReceivedFile(value))
+            }
+            (_, Value::Object(fields)) => visitor.visit_map(self.fields(fields, Mode::Value)),
+            (Mode::Text, Value::String(text)) => match guess(&text) {
+                Some(value) => value.deserialize_any(visitor),
+                None => visitor.visit_string(text),
+            },
+            (mode @ (Mode::Text | Mode::Value), Value::Array(values)) => {
+                visitor.visit_seq(self.list(values, mode))
+            }
+            (_, value) => value.deserialize_any(visitor),
+        }
+    }
+
+    deserialize_parsed! {
+        deserialize_bool => visit_bool,
+        deserialize_i8 => visit_i8,
+        deserialize_i16 => visit_i16,
+        deserialize_i32 => visit_i32,
+        deserialize_i64 => visit_i64,
+        deserialize_i128 => visit_i128,
+        deserialize_u8 => visit_u8,
+        deserialize_u16 => visit_u16,
+        deserialize_u32 => visit_u32,
+        deserialize_u64 => visit_u64,
+        deserialize_u128 => visit_u128,
+        deserialize_f32 => visit_f32,
+        deserialize_f64 => visit_f64,
+    }
+
+    fn deserialize_char<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_string(visitor)
+    }
+
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_string(visitor)
+    }
+
+    /// Files are decoded with the charset of their content type.
+    fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.into_text() {
+            Ok(text) => visitor.visit_string(text),
+            Err(deserializer)
+                if deserializer.mode == Mode::Text && is_file_value(&deserializer.value) =>
+            {
+                let file = file::from_value(deserializer.value)?;
+                visitor.visit_string(file.text().map_err(de::Error::custom)?)
+            }
+            Err(deserializer) => deserializer.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_identifier<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_string(visitor)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match (self.delimiter, &self.value) {
+            (Some(delimiter), Value::String(text)) if self.mode == Mode::Text => {
+                let items = delimiter.split(text);
+                visitor.visit_seq(self.list(items, Mode::Text))
+            }
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    /// A `Positioned` target gets the position of the part the value was read from.
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        if name == POSITIONED_TOKEN {
+            let position = self
+                .positions
+                .find(&self.path)
+                .ok_or_else(|| de::Error::custom("the value wasn't read from a part"))?;
+            return visitor.visit_map(PositionedFields::new(position, self));
+        }
+        match self.into_text() {
+            Ok(text) => visitor.visit_string(text),
+            Err(deserializer) => deserializer.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.value {
+            Value::Null => visitor.visit_none(),
+            _ => visitor.visit_some(self),
+        }
+    }
+
+    /// Text is read as a `text/plain` file when the target is a `File`, in a multipart/form-data
+    /// body only.
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        if name != FILE_TOKEN {
+            return visitor.visit_newtype_struct(self);
+        }
+        let files = self.files;
+        match self.into_text() {
+            Err(deserializer) if is_file_value(&deserializer.value) => {
+                visitor.visit_newtype_struct(ReceivedFile(deserializer.value))
+            }
+            _ if !files => Err(de::Error::custom(
+                "expected a file part, files can only be sent as parts of a multipart/form-data \
+                 or multipart/related body",
+            )),
+            Ok(text) => {
+                let part = PartData {
+                    data: text.into_bytes(),
+                    raw: None,
+                };
+                let value = file_value("", "text/plain; charset=utf-8", part, &HashMap::new());
+                visitor.visit_newtype_struct(ReceivedFile(value))
+            }
+            Err(deserializer) => Err(de::Error::invalid_type(
+                unexpected(&deserializer.value),
+                &"a file part",
+            )),
+        }
+    }
+
+    /// Text is a unit variant, other values are deserialized as usual: an object with a single
+    /// key for the other variants.
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        match self.into_text() {
+            Ok(text) => visitor.visit_enum(text.into_deserializer()),
+            Err(mut deserializer) if deserializer.mode != Mode::Fields => {
+                match std::mem::take(&mut deserializer.value) {
+                    Value::Object(fields) => {
+                        MapAccessDeserializer::new(deserializer.fields(fields, Mode::Value))
+                            .deserialize_enum(name, variants, visitor)
+                    }
+                    value => value.deserialize_enum(name, variants, visitor),
+                }
+            }
+            Err(deserializer) => deserializer.value.deserialize_enum(name, variants, visitor),
+        }
+    }
+
+    /// A `CsvFile` gets the records of a file parsed while it was received, or the file.
+    #[cfg_attr(not(feature = "csv"), allow(unused_variables))]
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        #[cfg(feature = "csv")]
+        if name == CSV_TOKEN {
+            return match self.value {
+                Value::Object(mut fields) if fields.contains_key(CSV_TOKEN) => {
+                    fields.remove(CSV_TOKEN);
+                    Value::Object(fields).deserialize_map(visitor)
+                }
+                _ => visitor.visit_newtype_struct(self),
+            };
+        }
+        self.deserialize_any(visitor)
+    }
+
+    forward_to_deserialize_any! {
+        bytes byte_buf unit unit_struct tuple map ignored_any
+    }
+}
+
+/// A file collected by `file_value`. `File` reads the newtype this deserializer passes as a
+/// received file, digests included.
+struct ReceivedFile(Value);
+
+impl<'de> de::Deserializer<'de> for ReceivedFile {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let mut value = self.0;
+        if let Value::Object(fields) = &mut value {
+            fields.remove(FILE_TOKEN);
+        }
+        visitor.visit_newtype_struct(value)
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf
+        option unit unit_struct newtype_struct seq tuple tuple_struct map struct enum
+        identifier ignored_any
+    }
+}
+
+/// Deserializes a field with its `FromStr` implementation, for text inside internally tagged
+/// enums and flattened fields, which may have been read as a number or a boolean:
+///
+/// ```no_run
+/// # use serde::Deserialize;
+/// #[derive(Deserialize)]
+/// #[serde(tag = "kind")]
+/// enum Media {
+///     Photo {
+///         #[serde(deserialize_with = "actix_extract_multipart::deserialize_from_str")]
+///         caption: String,
+///     },
+/// }
+/// ```
+pub fn deserialize_from_str<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: de::Deserializer<'de>,
+    T: FromStr,
+    T::Err: fmt::Display,
+{
+    deserialize_text(deserializer)?
+        .parse()
+        .map_err(de::Error::custom)
+}
+
+/// Reads a text value back, whether it was seen as text, a number or a boolean.
+pub(crate) fn deserialize_text<'de, D: de::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<String, D::Error> {
+    deserializer.deserialize_any(TextVisitor)
+}
+
+#[cfg_attr(not(any(feature = "chrono", feature = "time")), allow(dead_code))]
+pub(crate) fn deserialize_optional_text<'de, D: de::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<String>, D::Error> {
+    deserializer.deserialize_option(OptionalTextVisitor)
+}
+
+struct TextVisitor;
+
+impl Visitor<'_> for TextVisitor {
+    type Value = String;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a text value")
+    }
+
+    fn visit_str<E: de::Error>(self, text: &str) -> Result<String, E> {
+        Ok(text.to_owned())
+    }
+
+    fn visit_bool<E: de::Error>(self, value: bool) -> Result<String, E> {
+        Ok(value.to_string())
+    }
+
+    fn visit_i64<E: de::Error>(self, value: i64) -> Result<String, E> {
+        Ok(value.to_string())
+    }
+
+    fn visit_u64<E: de::Error>(self, value: u64) -> Result<String, E> {
+        Ok(value.to_string())
+    }
+
+    fn visit_f64<E: de::Error>(self, value: f64) -> Result<String, E> {
+        Ok(Value::from(value).to_string())
+    }
+}
+
+#[cfg_attr(not(any(feature = "chrono", feature = "time")), allow(dead_code))]
+struct OptionalTextVisitor;
+
+impl<'de> Visitor<'de> for OptionalTextVisitor {
+    type Value = Option<String>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("an optional text value")
+    }
+
+    fn visit_none<E: de::Error>(self) -> Result<Option<String>, E> {
+        Ok(None)
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<Option<String>, E> {
+        Ok(None)
+    }
+
+    fn visit_some<D: de::Deserializer<'de>>(
+        self,
+        deserializer: D,
+    ) -> Result<Option<String>, D::Error> {
+        deserialize_text(deserializer).map(Some)
+    }
+}
+
+/// The collected fields, or the fields of an object inside a structured value.
+struct Fields<'a> {
+    fields: serde_json::map::IntoIter,
+    /// The value of the current key, its delimiter and its path.
+    value: Option<(Value, Option<Delimiter>, String)>,
+    /// Mode of the values.
+    mode: Mode,
+    delimiters: &'a Delimiters,
+    files: bool,
+    positions: &'a Positions,
+    /// Path of the object.
+    path: String,
+}
+
+impl<'de> MapAccess<'de> for Fields<'_> {
+    type Error = Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Error> {
+        match self.fields.next() {
+            Some((name, value)) => {
+                let delimiter = match self.mode {
+                    Mode::Text => self
+                        .delimiters
+                        .fields
+                        .get(&name)
+                        .or(self.delimiters.all.as_ref())
+                        .copied(),
+                    _ => None,
+                };
+                let path = match self.path.is_empty() {
+                    true => name.clone(),
+                    false => format!("{}.{}", self.path, name),
+                };
+                self.value = Some((value, delimiter, path));
+                seed.deserialize(StringDeserializer::new(name)).map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, Error> {
+        let (value, delimiter, path) = self.value.take().unwrap_or_default();
+        seed.deserialize(FieldDeserializer {
+            value,
+            mode: self.mode,
+            delimiters: self.delimiters,
+            files: self.files,
+            delimiter,
+            positions: self.positions,
+            path,
+        })
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.fields.len())
+    }
+}
+
+struct List<'a> {
+    values: std::iter::Enumerate<std::vec::IntoIter<Value>>,
+    /// Mode of the items.
+    mode: Mode,
+    delimiters: &'a Delimiters,
+    files: bool,
+    positions: &'a Positions,
+    /// Path of the list.
+    path: String,
+}
+
+impl<'de> SeqAccess<'de> for List<'_> {
+    type Error = Error;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, Error> {
+        self.values
+            .next()
+            .map(|(index, value)| {
+                seed.deserialize(FieldDeserializer {
+                    value,
+                    mode: self.mode,
+                    delimiters: self.delimiters,
+                    files: self.files,
+                    delimiter: None,
+                    positions: self.positions,
+                    path: format!("{}[{}]", self.path, index),
+                })
+            })
+            .transpose()
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.values.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Delimiter;
+    use crate::{File, Multipart, MultipartBuilder, MultipartConfig, Part};
+    use actix_web::FromRequest;
+    use serde::Deserialize;
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    #[serde(rename_all = "lowercase")]
+    enum Status {
+        Draft,
+        Published,
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    #[serde(tag = "kind", rename_all = "lowercase")]
+    enum Media {
+        Photo {
+            width: u32,
+            #[serde(deserialize_with = "super::deserialize_from_str")]
+            caption: String,
+        },
+        Video {
+            duration: f64,
+        },
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    enum Shape {
+        Circle { radius: u32 },
+        Empty,
+    }
+
+    #[derive(Deserialize)]
+    struct Post {
+        status: Status,
+        statuses: Vec<Status>,
+        #[serde(flatten)]
+        media: Media,
+        shape: Shape,
+        empty: Shape,
+    }
+
+    async fn extract<T: serde::de::DeserializeOwned + 'static>(
+        builder: MultipartBuilder,
+    ) -> Result<Multipart<T>, actix_web::Error> {
+        extract_with(builder, MultipartConfig::default()).await
+    }
+
+    async fn extract_with<T: serde::de::DeserializeOwned + 'static>(
+        builder: MultipartBuilder,
+        config: MultipartConfig,
+    ) -> Result<Multipart<T>, actix_web::Error> {
+        let (req, mut payload) = builder
+            .test_request()
+            .unwrap()
+            .app_data(config)
+            .to_http_parts();
+        Multipart::<T>::from_request(&req, &mut payload).await
+    }
+
+    #[actix_rt::test]
+    async fn test_enums() {
+        let builder = MultipartBuilder::new()
+            .text("status", "draft")
+            .text("statuses[]", "published")
+            .text("statuses[]", "draft")
+            .text("kind", "photo")
+            .text("width", "640")
+            .text("caption", "2024")
+            .part(
+                Part::text("shape", r#"{"Circle": {"radius": 3}}"#)
+                    .header("Content-Type", "application/json"),
+            )
+            .text("empty", "Empty");
+
+        match extract::<Post>(builder).await {
+            Ok(data) => {
+                assert_eq!(data.status, Status::Draft);
+                assert_eq!(data.statuses, [Status::Published, Status::Draft]);
+                assert_eq!(
+                    data.media,
+                    Media::Photo {
+                        width: 640,
+                        caption: "2024".to_owned()
+                    }
+                );
+                assert_eq!(data.shape, Shape::Circle { radius: 3 });
+                assert_eq!(data.empty, Shape::Empty);
+            }
+            Err(_) => panic!("Failed to parse multipart into structure"),
+        }
+    }
+
+    #[allow(dead_code)]
+    #[derive(Deserialize)]
+    struct Types {
+        year: String,
+        code: String,
+        id: uuid::Uuid,
+        date: chrono::NaiveDate,
+        #[serde(with = "time::serde::rfc3339")]
+        at: time::OffsetDateTime,
+        price: rust_decimal::Decimal,
+        ip: std::net::IpAddr,
+        enabled: bool,
+    }
+
+    #[actix_rt::test]
+    async fn test_text_types() {
+        let builder = MultipartBuilder::new()
+            .text("year", "2024")
+            .text("code", "007")
+            .text("id", "67e55044-10b1-426f-9247-bb680e5fe0c8")
+            .text("date", "2024-02-29")
+            .text("at", "2024-02-29T10:30:00+01:00")
+            .text("price", "1.50")
+            .text("ip", "::1")
+            .text("enabled", "true");
+
+        match extract::<Types>(builder).await {
+            Ok(data) => {
+                assert_eq!(data.year, "2024");
+                assert_eq!(data.code, "007");
+                assert_eq!(data.id.to_string(), "67e55044-10b1-426f-9247-bb680e5fe0c8");
+                assert_eq!(data.date.to_string(), "2024-02-29");
+                assert_eq!(data.at.unix_timestamp(), 1_709_199_000);
+                assert_eq!(data.price.to_string(), "1.50");
+                assert!(data.ip.is_loopback());
+                assert!(data.enabled);
+            }
+            Err(_) => panic!("Failed to parse multipart into structure"),
+        }
+
+        let builder = MultipartBuilder::new()
+            .text("year", "2024")
+            .text("code", "007")
+            .text("id", "not-a-uuid")
+            .text("date", "2024-02-29")
+            .text("at", "2024-02-29T10:30:00+01:00")
+            .text("price", "1.50")
+            .text("ip", "::1")
+            .text("enabled", "true");
+        let err = extract::<Types>(builder).await.err().unwrap();
+        assert!(err
+            .to_string()
+            .starts_with("Invalid value for field \"id\""));
+    }
+
+    #[derive(Deserialize)]
+    struct Lists {
+        tags: Vec<String>,
+        ids: Vec<u32>,
+        statuses: Option<Vec<Status>>,
+        codes: Vec<String>,
+    }
+
+    #[actix_rt::test]
+    async fn test_delimited_lists() {
+        let config = MultipartConfig::default()
+            .list_delimiter(Delimiter::Whitespace)
+            .field_delimiter("tags", Delimiter::Comma);
+        let builder = MultipartBuilder::new()
+            .text("tags", "a, b c,,d")
+            .text("ids", "1 2\n 3")
+            .text("statuses", "draft published")
+            .text("codes[]", "x y")
+            .text("codes[]", "z");
+
+        match extract_with::<Lists>(builder, config.clone()).await {
+            Ok(data) => {
+                assert_eq!(data.tags, ["a", "b c", "d"]);
+                assert_eq!(data.ids, [1, 2, 3]);
+                assert_eq!(
+                    data.statuses.as_deref(),
+                    Some(&[Status::Draft, Status::Published][..])
+                );
+                assert_eq!(data.codes, ["x y", "z"]);
+            }
+            Err(_) => panic!("Failed to parse multipart into structure"),
+        }
+
+        let builder = MultipartBuilder::new()
+            .text("tags", "a")
+            .text("ids", "1 two")
+            .text("codes[]", "x");
+        let err = extract_with::<Lists>(builder, config).await.err().unwrap();
+        assert!(err
+            .to_string()
+            .starts_with("Invalid value for field \"ids[1]\""));
+
+        let builder = MultipartBuilder::new()
+            .text("tags", "a,b")
+            .text("ids", "1")
+            .text("codes[]", "x");
+        assert!(extract::<Lists>(builder).await.is_err());
+    }
+
+    #[allow(dead_code)]
+    #[derive(Deserialize)]
+    struct TextFiles {
+        config: String,
+        notes: Vec<String>,
+        comment: File,
+        attachment: Option<File>,
+    }
+
+    #[actix_rt::test]
+    async fn test_files_and_text() {
+        let builder = MultipartBuilder::new()
+            .file(
+                "config",
+                "config.toml",
+                "text/plain; charset=iso-8859-1",
+                b"caf\xe9".to_vec(),
+            )
+            .file("notes[]", "a.md", "text/markdown", "\u{feff}# A")
+            .file(
+                "notes[]",
+                "b.md",
+                "text/markdown; charset=utf-16le",
+                b"\xff\xfeB\x00".to_vec(),
+            )
+            .text("comment", "Some text")
+            .text("attachment", "More text");
+
+        match extract::<TextFiles>(builder).await {
+            Ok(data) => {
+                assert_eq!(data.config, "café");
+                assert_eq!(data.notes, ["# A", "B"]);
+                assert_eq!(data.comment.data(), b"Some text");
+                assert_eq!(data.comment.file_type(), "text/plain; charset=utf-8");
+                assert_eq!(
+                    data.attachment.as_ref().unwrap().text().unwrap(),
+                    "More text"
+                );
+            }
+            Err(_) => panic!("Failed to parse multipart into structure"),
+        }
+
+        let builder = MultipartBuilder::new()
+            .file("config", "config.toml", "text/plain", b"caf\xe9".to_vec())
+            .text("notes[]", "a")
+            .text("comment", "Some text");
+        let err = extract::<TextFiles>(builder).await.err().unwrap();
+        assert!(err.to_string().contains("not valid UTF-8 text"));
+
+        mod own {
+            #[allow(dead_code)]
+            #[derive(serde::Deserialize)]
+            pub struct File {
+                pub name: String,
+            }
+        }
+        #[allow(dead_code)]
+        #[derive(Deserialize)]
+        struct OwnFile {
+            comment: own::File,
+        }
+        let builder = MultipartBuilder::new().text("comment", "Some text");
+        assert!(extract::<OwnFile>(builder).await.is_err());
+    }
+
+    #[actix_rt::test]
+    async fn test_internally_tagged_root() {
+        let builder = MultipartBuilder::new()
+            .text("kind", "video")
+            .text("duration", "12.5");
+        match extract::<Media>(builder).await {
+            Ok(data) => assert_eq!(*data, Media::Video { duration: 12.5 }),
+            Err(_) => panic!("Failed to parse multipart into structure"),
+        }
+
+        let builder = MultipartBuilder::new()
+            .text("kind", "audio")
+            .text("duration", "12.5");
+        assert!(extract::<Media>(builder).await.is_err());
+
+        let builder = MultipartBuilder::new()
+            .text("status", "archived")
+            .text("statuses[]", "draft")
+            .text("kind", "video")
+            .text("duration", "1")
+            .text("shape", "Empty")
+            .text("empty", "Empty");
+        let err = extract::<Post>(builder).await.err().unwrap();
+        assert!(err.to_string().contains("unknown variant `archived`"));
+    }
+}
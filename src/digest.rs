@@ -0,0 +1,326 @@
+use actix_web::http::header::{HeaderMap, HeaderName, HeaderValue};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use serde::Deserialize;
+use std::collections::HashMap;
+
+/// Checksum algorithms that can be computed while a part is being received. Each one is
+/// enabled by the feature of the same name: `sha2`, `sha1`, `md5`, `crc32c` and `blake3`.
+///
+/// The serialized names follow the IANA "HTTP Digest Algorithm Values" registry,
+/// which is also what the `Digest` and `Repr-Digest` part headers use.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[non_exhaustive]
+pub enum Algorithm {
+    #[cfg(feature = "sha2")]
+    #[serde(rename = "sha-256")]
+    Sha256,
+    #[cfg(feature = "sha1")]
+    #[serde(rename = "sha")]
+    Sha1,
+    #[cfg(feature = "md5")]
+    #[serde(rename = "md5")]
+    Md5,
+    #[cfg(feature = "crc32c")]
+    #[serde(rename = "crc32c")]
+    Crc32c,
+    #[cfg(feature = "blake3")]
+    #[serde(rename = "blake3")]
+    Blake3,
+}
+
+impl Algorithm {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            #[cfg(feature = "sha2")]
+            Algorithm::Sha256 => "sha-256",
+            #[cfg(feature = "sha1")]
+            Algorithm::Sha1 => "sha",
+            #[cfg(feature = "md5")]
+            Algorithm::Md5 => "md5",
+            #[cfg(feature = "crc32c")]
+            Algorithm::Crc32c => "crc32c",
+            #[cfg(feature = "blake3")]
+            Algorithm::Blake3 => "blake3",
+        }
+    }
+
+    pub(crate) fn from_name(name: &str) -> Option<Algorithm> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            #[cfg(feature = "sha2")]
+            "sha-256" => Some(Algorithm::Sha256),
+            #[cfg(feature = "sha1")]
+            "sha" | "sha-1" => Some(Algorithm::Sha1),
+            #[cfg(feature = "md5")]
+            "md5" => Some(Algorithm::Md5),
+            #[cfg(feature = "crc32c")]
+            "crc32c" => Some(Algorithm::Crc32c),
+            #[cfg(feature = "blake3")]
+            "blake3" => Some(Algorithm::Blake3),
+            _ => None,
+        }
+    }
+
+    fn hasher(self) -> Box<dyn Hasher> {
+        match self {
+            #[cfg(feature = "sha2")]
+            Algorithm::Sha256 => Box::new(<sha2::Sha256 as sha2::Digest>::new()),
+            #[cfg(feature = "sha1")]
+            Algorithm::Sha1 => Box::new(<sha1::Sha1 as sha1::Digest>::new()),
+            #[cfg(feature = "md5")]
+            Algorithm::Md5 => Box::new(<md5::Md5 as md5::Digest>::new()),
+            #[cfg(feature = "crc32c")]
+            Algorithm::Crc32c => Box::new(Crc32c(0)),
+            #[cfg(feature = "blake3")]
+            Algorithm::Blake3 => Box::new(blake3::Hasher::new()),
+        }
+    }
+}
+
+trait Hasher {
+    fn update(&mut self, data: &[u8]);
+    fn finalize(self: Box<Self>) -> Vec<u8>;
+}
+
+#[cfg(feature = "sha2")]
+impl Hasher for sha2::Sha256 {
+    fn update(&mut self, data: &[u8]) {
+        sha2::Digest::update(self, data)
+    }
+
+    fn finalize(self: Box<Self>) -> Vec<u8> {
+        sha2::Digest::finalize(*self).to_vec()
+    }
+}
+
+#[cfg(feature = "sha1")]
+impl Hasher for sha1::Sha1 {
+    fn update(&mut self, data: &[u8]) {
+        sha1::Digest::update(self, data)
+    }
+
+    fn finalize(self: Box<Self>) -> Vec<u8> {
+        sha1::Digest::finalize(*self).to_vec()
+    }
+}
+
+#[cfg(feature = "md5")]
+impl Hasher for md5::Md5 {
+    fn update(&mut self, data: &[u8]) {
+        md5::Digest::update(self, data)
+    }
+
+    fn finalize(self: Box<Self>) -> Vec<u8> {
+        md5::Digest::finalize(*self).to_vec()
+    }
+}
+
+#[cfg(feature = "crc32c")]
+struct Crc32c(u32);
+
+#[cfg(feature = "crc32c")]
+impl Hasher for Crc32c {
+    fn update(&mut self, data: &[u8]) {
+        self.0 = crc32c::crc32c_append(self.0, data);
+    }
+
+    fn finalize(self: Box<Self>) -> Vec<u8> {
+        self.0.to_be_bytes().to_vec()
+    }
+}
+
+#[cfg(feature = "blake3")]
+impl Hasher for blake3::Hasher {
+    fn update(&mut self, data: &[u8]) {
+        blake3::Hasher::update(self, data);
+    }
+
+    fn finalize(self: Box<Self>) -> Vec<u8> {
+        blake3::Hasher::finalize(&self).as_bytes().to_vec()
+    }
+}
+
+/// Runs every requested algorithm over the chunks of a single part.
+pub(crate) struct Digester {
+    hashers: Vec<(Algorithm, Box<dyn Hasher>)>,
+}
+
+impl Digester {
+    pub(crate) fn new<I>(algorithms: I) -> Self
+    where
+        I: IntoIterator<Item = Algorithm>,
+    {
+        let mut hashers: Vec<(Algorithm, Box<dyn Hasher>)> = Vec::new();
+        for algorithm in algorithms {
+            if !hashers.iter().any(|(a, _)| *a == algorithm) {
+                hashers.push((algorithm, algorithm.hasher()));
+            }
+        }
+        Digester { hashers }
+    }
+
+    pub(crate) fn update(&mut self, data: &[u8]) {
+        for (_, hasher) in self.hashers.iter_mut() {
+            hasher.update(data);
+        }
+    }
+
+    pub(crate) fn finalize(self) -> HashMap<Algorithm, Vec<u8>> {
+        self.hashers
+            .into_iter()
+            .map(|(algorithm, hasher)| (algorithm, hasher.finalize()))
+            .collect()
+    }
+}
+
+/// Digests announced by a part through its `Content-MD5`, `Digest` (RFC 3230) or
+/// `Repr-Digest` (RFC 9530) headers.
+#[derive(Debug, Default)]
+pub(crate) struct ExpectedDigests {
+    /// From `Content-MD5` and `Digest`, checked against the decoded bytes.
+    pub(crate) content: Vec<(Algorithm, Vec<u8>)>,
+    /// From `Repr-Digest`, checked against the bytes before their `Content-Encoding` is
+    /// decoded, as the representation they describe is still encoded.
+    pub(crate) representation: Vec<(Algorithm, Vec<u8>)>,
+}
+
+impl ExpectedDigests {
+    /// A header whose algorithms are all unknown or disabled can't be verified, and its
+    /// first algorithm is returned as the error. Algorithms sent along a supported one are
+    /// skipped. A value that isn't valid base64 is kept as an empty digest so that
+    /// verification fails instead of being skipped.
+    pub(crate) fn from_headers(headers: &HeaderMap) -> Result<Self, String> {
+        let mut expected = ExpectedDigests::default();
+
+        #[cfg(not(feature = "md5"))]
+        if headers.contains_key(HeaderName::from_static("content-md5")) {
+            return Err("md5".to_owned());
+        }
+        #[cfg(feature = "md5")]
+        for value in headers.get_all(HeaderName::from_static("content-md5")) {
+            let value = value.to_str().unwrap_or_default();
+            expected
+                .content
+                .push((Algorithm::Md5, decode_base64(value)));
+        }
+
+        for value in headers.get_all(HeaderName::from_static("digest")) {
+            expected.content.extend(parse_digests(value)?);
+        }
+        for value in headers.get_all(HeaderName::from_static("repr-digest")) {
+            expected.representation.extend(parse_digests(value)?);
+        }
+
+        Ok(expected)
+    }
+
+    /// Algorithms to compute over the decoded bytes.
+    pub(crate) fn content_algorithms(&self) -> impl Iterator<Item = Algorithm> + '_ {
+        self.content.iter().map(|(algorithm, _)| *algorithm)
+    }
+
+    /// Computes the `Repr-Digest` algorithms over the bytes before decompression.
+    pub(crate) fn representation_digester(&self) -> Digester {
+        Digester::new(self.representation.iter().map(|(algorithm, _)| *algorithm))
+    }
+}
+
+/// Entries of a `Digest` or `Repr-Digest` header, e.g. `sha-256=:...:, md5=...`.
+fn parse_digests(value: &HeaderValue) -> Result<Vec<(Algorithm, Vec<u8>)>, String> {
+    let value = String::from_utf8_lossy(value.as_bytes());
+    let mut digests = Vec::new();
+    let mut unsupported = None;
+
+    for entry in value.split(',') {
+        let (name, encoded) = match entry.split_once('=') {
+            Some(pair) => pair,
+            None => continue,
+        };
+        match Algorithm::from_name(name) {
+            Some(algorithm) => {
+                // Repr-Digest wraps the value in a structured field byte sequence: `:...:`
+                let encoded = encoded.trim().trim_matches(':');
+                digests.push((algorithm, decode_base64(encoded)));
+            }
+            None => {
+                unsupported.get_or_insert_with(|| name.trim().to_ascii_lowercase());
+            }
+        }
+    }
+
+    match (digests.is_empty(), unsupported) {
+        (true, Some(name)) => Err(name),
+        _ => Ok(digests),
+    }
+}
+
+fn decode_base64(value: &str) -> Vec<u8> {
+    BASE64.decode(value.trim()).unwrap_or_default()
+}
+
+#[cfg(all(test, feature = "sha2", feature = "md5"))]
+mod tests {
+    use super::*;
+
+    #[cfg(all(feature = "sha1", feature = "crc32c", feature = "blake3"))]
+    #[test]
+    fn test_known_digest_values() {
+        let mut digester = Digester::new([
+            Algorithm::Sha256,
+            Algorithm::Sha1,
+            Algorithm::Md5,
+            Algorithm::Crc32c,
+            Algorithm::Blake3,
+        ]);
+        digester.update(b"te");
+        digester.update(b"st");
+        let digests = digester.finalize();
+
+        assert_eq!(
+            BASE64.encode(&digests[&Algorithm::Md5]),
+            "CY9rzUYh03PK3k6DJie09g=="
+        );
+        assert_eq!(
+            BASE64.encode(&digests[&Algorithm::Sha256]),
+            "n4bQgYhMfWWaL+qgxVrQFaO/TxsrC4Is0V1sFbDwCgg="
+        );
+        assert_eq!(digests[&Algorithm::Crc32c], vec![0x86, 0xa0, 0x72, 0xc0]);
+        assert_eq!(digests.len(), 5);
+    }
+
+    #[test]
+    fn test_expected_digests_from_headers() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            HeaderName::from_static("digest"),
+            HeaderValue::from_static(
+                "SHA-256=n4bQgYhMfWWaL+qgxVrQFaO/TxsrC4Is0V1sFbDwCgg=,UNIXsum=30637",
+            ),
+        );
+        headers.insert(
+            HeaderName::from_static("repr-digest"),
+            HeaderValue::from_static("md5=:CY9rzUYh03PK3k6DJie09g==:"),
+        );
+
+        let expected = ExpectedDigests::from_headers(&headers).unwrap();
+        assert_eq!(expected.content.len(), 1);
+        assert_eq!(expected.content[0].0, Algorithm::Sha256);
+        assert_eq!(expected.representation.len(), 1);
+        assert_eq!(expected.representation[0].0, Algorithm::Md5);
+        assert_eq!(expected.representation[0].1.len(), 16);
+    }
+
+    #[test]
+    fn test_unsupported_digest_rejected() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            HeaderName::from_static("digest"),
+            HeaderValue::from_static("UNIXsum=30637"),
+        );
+
+        assert_eq!(
+            ExpectedDigests::from_headers(&headers).unwrap_err(),
+            "unixsum"
+        );
+    }
+}
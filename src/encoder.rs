@@ -0,0 +1,953 @@
+//! Serialization of a structure into a `multipart/form-data` body, the reverse of `Multipart<T>`.
+//!
+//! Each field becomes a text part, `File` fields become file parts and sequences become
+//! repeated `name[]` parts, so a structure serialized with `to_multipart` can be extracted
+//! again with `Multipart<T>`. Byte buffers become `application/octet-stream` file parts named
+//! after their field, to read back as a `File`. Like the extractor, which skips empty parts,
+//! empty text, empty sequences and empty files aren't sent, except empty text inside a
+//! sequence, which keeps its place in the list.
+//!
+//! `File` fields are serialized with their data, as `FileRepr::Base64`.
+
+use crate::builder::{MultipartBuilder, Part};
+use crate::file::{self, FILE_TOKEN};
+use actix_web::web::Bytes;
+use futures::stream::{self, Stream};
+use serde::ser::{self, Impossible, Serialize, SerializeStruct, Serializer};
+use std::fmt;
+
+pub(crate) struct RawBytes<'a>(pub(crate) &'a [u8]);
+
+impl Serialize for RawBytes<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_bytes(self.0)
+    }
+}
+
+#[derive(Debug)]
+pub struct EncodeError(String);
+
+impl fmt::Display for EncodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for EncodeError {}
+
+impl ser::Error for EncodeError {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        EncodeError(msg.to_string())
+    }
+}
+
+fn unsupported(what: &str) -> EncodeError {
+    EncodeError(format!("{} can't be encoded as a multipart part", what))
+}
+
+/// Serializes a structure (or a map) into a multipart body.
+///
+/// Returns the boundary, to send in `Content-Type: multipart/form-data; boundary=...`,
+/// and the body. The value is serialized before this returns, with the data of its files
+/// copied into memory; the stream then writes the parts from there, one at a time.
+///
+/// ```no_run
+/// # use actix_extract_multipart::{to_multipart, File};
+/// # use serde::Serialize;
+/// #[derive(Serialize)]
+/// struct Upload {
+///     title: String,
+///     file: File,
+/// }
+///
+/// # fn upload(upload: &Upload) -> Result<(), actix_extract_multipart::EncodeError> {
+/// let (boundary, body) = to_multipart(upload)?;
+/// let content_type = format!("multipart/form-data; boundary={}", boundary);
+/// # Ok(())
+/// # }
+/// ```
+pub fn to_multipart<T>(value: &T) -> Result<(String, impl Stream<Item = Bytes>), EncodeError>
+where
+    T: Serialize + ?Sized,
+{
+    let builder = to_multipart_builder(value)?;
+    let boundary = builder.get_boundary().to_owned();
+    let chunks = builder
+        .into_chunks()
+        .map_err(|err| EncodeError(err.to_string()))?;
+    Ok((boundary, stream::iter(chunks)))
+}
+
+pub(crate) fn to_multipart_builder<T>(value: &T) -> Result<MultipartBuilder, EncodeError>
+where
+    T: Serialize + ?Sized,
+{
+    let mut parts = Vec::new();
+    file::encoding(|| value.serialize(RootSerializer { parts: &mut parts }))?;
+
+    Ok(parts
+        .into_iter()
+        .fold(MultipartBuilder::new(), |builder, part| builder.part(part)))
+}
+
+macro_rules! unsupported_methods {
+    ($what:expr; $($method:ident($($arg:ty),*)),* $(,)?) => {
+        $(
+            fn $method(self, $(_: $arg),*) -> Result<Self::Ok, Self::Error> {
+                Err(unsupported($what))
+            }
+        )*
+    };
+}
+
+struct RootSerializer<'a> {
+    parts: &'a mut Vec<Part>,
+}
+
+impl<'a> Serializer for RootSerializer<'a> {
+    type Ok = ();
+    type Error = EncodeError;
+    type SerializeSeq = Impossible<(), EncodeError>;
+    type SerializeTuple = Impossible<(), EncodeError>;
+    type SerializeTupleStruct = Impossible<(), EncodeError>;
+    type SerializeTupleVariant = Impossible<(), EncodeError>;
+    type SerializeMap = MapEncoder<'a>;
+    type SerializeStruct = StructEncoder<'a>;
+    type SerializeStructVariant = Impossible<(), EncodeError>;
+
+    fn is_human_readable(&self) -> bool {
+        false
+    }
+
+    unsupported_methods! {
+        "Only structures and maps";
+        serialize_bool(bool), serialize_i8(i8), serialize_i16(i16), serialize_i32(i32),
+        serialize_i64(i64), serialize_u8(u8), serialize_u16(u16), serialize_u32(u32),
+        serialize_u64(u64), serialize_f32(f32), serialize_f64(f64), serialize_char(char),
+        serialize_str(&str), serialize_bytes(&[u8]), serialize_none(), serialize_unit(),
+        serialize_unit_struct(&'static str),
+        serialize_unit_variant(&'static str, u32, &'static str),
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<(), EncodeError> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _: &'static str,
+        value: &T,
+    ) -> Result<(), EncodeError> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+        _: &T,
+    ) -> Result<(), EncodeError> {
+        Err(unsupported("An enum"))
+    }
+
+    fn serialize_seq(self, _: Option<usize>) -> Result<Self::SerializeSeq, EncodeError> {
+        Err(unsupported("A sequence"))
+    }
+
+    fn serialize_tuple(self, _: usize) -> Result<Self::SerializeTuple, EncodeError> {
+        Err(unsupported("A tuple"))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self::SerializeTupleStruct, EncodeError> {
+        Err(unsupported("A tuple"))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self::SerializeTupleVariant, EncodeError> {
+        Err(unsupported("An enum"))
+    }
+
+    fn serialize_map(self, _: Option<usize>) -> Result<Self::SerializeMap, EncodeError> {
+        Ok(MapEncoder {
+            parts: self.parts,
+            key: None,
+        })
+    }
+
+    fn serialize_struct(
+        self,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self::SerializeStruct, EncodeError> {
+        Ok(StructEncoder { parts: self.parts })
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self::SerializeStructVariant, EncodeError> {
+        Err(unsupported("An enum"))
+    }
+}
+
+struct StructEncoder<'a> {
+    parts: &'a mut Vec<Part>,
+}
+
+impl ser::SerializeStruct for StructEncoder<'_> {
+    type Ok = ();
+    type Error = EncodeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), EncodeError> {
+        value.serialize(PartSerializer {
+            name: key.to_owned(),
+            parts: self.parts,
+            in_list: false,
+        })
+    }
+
+    fn end(self) -> Result<(), EncodeError> {
+        Ok(())
+    }
+}
+
+struct MapEncoder<'a> {
+    parts: &'a mut Vec<Part>,
+    key: Option<String>,
+}
+
+impl ser::SerializeMap for MapEncoder<'_> {
+    type Ok = ();
+    type Error = EncodeError;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), EncodeError> {
+        self.key = Some(key.serialize(TextSerializer)?);
+        Ok(())
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), EncodeError> {
+        let name = self
+            .key
+            .take()
+            .ok_or_else(|| EncodeError("Map value serialized before its key".to_owned()))?;
+        value.serialize(PartSerializer {
+            name,
+            parts: self.parts,
+            in_list: false,
+        })
+    }
+
+    fn end(self) -> Result<(), EncodeError> {
+        Ok(())
+    }
+}
+
+/// Serializes the value of one field into zero, one or several parts.
+struct PartSerializer<'a> {
+    name: String,
+    parts: &'a mut Vec<Part>,
+    in_list: bool,
+}
+
+impl PartSerializer<'_> {
+    fn text(self, value: String) -> Result<(), EncodeError> {
+        if !value.is_empty() || self.in_list {
+            self.parts.push(Part::text(self.name, value));
+        }
+        Ok(())
+    }
+}
+
+impl<'a> Serializer for PartSerializer<'a> {
+    type Ok = ();
+    type Error = EncodeError;
+    type SerializeSeq = ListEncoder<'a>;
+    type SerializeTuple = ListEncoder<'a>;
+    type SerializeTupleStruct = ListEncoder<'a>;
+    type SerializeTupleVariant = Impossible<(), EncodeError>;
+    type SerializeMap = Impossible<(), EncodeError>;
+    type SerializeStruct = FileEncoder<'a>;
+    type SerializeStructVariant = Impossible<(), EncodeError>;
+
+    fn is_human_readable(&self) -> bool {
+        false
+    }
+
+    fn serialize_bool(self, v: bool) -> Result<(), EncodeError> {
+        self.text(v.to_string())
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<(), EncodeError> {
+        self.text(v.to_string())
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<(), EncodeError> {
+        self.text(v.to_string())
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<(), EncodeError> {
+        self.text(v.to_string())
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<(), EncodeError> {
+        self.text(v.to_string())
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<(), EncodeError> {
+        self.text(v.to_string())
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<(), EncodeError> {
+        self.text(v.to_string())
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<(), EncodeError> {
+        self.text(v.to_string())
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<(), EncodeError> {
+        self.text(v.to_string())
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<(), EncodeError> {
+        self.text(v.to_string())
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<(), EncodeError> {
+        self.text(v.to_string())
+    }
+
+    fn serialize_char(self, v: char) -> Result<(), EncodeError> {
+        self.text(v.to_string())
+    }
+
+    fn serialize_str(self, v: &str) -> Result<(), EncodeError> {
+        self.text(v.to_owned())
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<(), EncodeError> {
+        if !v.is_empty() {
+            let filename = self.name.replace("[]", "");
+            self.parts.push(Part::file(
+                self.name,
+                filename,
+                "application/octet-stream",
+                Bytes::copy_from_slice(v),
+            ));
+        }
+        Ok(())
+    }
+
+    fn serialize_none(self) -> Result<(), EncodeError> {
+        Ok(())
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<(), EncodeError> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<(), EncodeError> {
+        Ok(())
+    }
+
+    fn serialize_unit_struct(self, _: &'static str) -> Result<(), EncodeError> {
+        Ok(())
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _: &'static str,
+        _: u32,
+        variant: &'static str,
+    ) -> Result<(), EncodeError> {
+        self.text(variant.to_owned())
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _: &'static str,
+        value: &T,
+    ) -> Result<(), EncodeError> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+        _: &T,
+    ) -> Result<(), EncodeError> {
+        Err(unsupported("An enum with data"))
+    }
+
+    fn serialize_seq(self, _: Option<usize>) -> Result<Self::SerializeSeq, EncodeError> {
+        if self.in_list {
+            return Err(unsupported("A nested sequence"));
+        }
+        let name = if self.name.ends_with("[]") {
+            self.name
+        } else {
+            format!("{}[]", self.name)
+        };
+        Ok(ListEncoder {
+            name,
+            parts: self.parts,
+        })
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<Self::SerializeTuple, EncodeError> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeTupleStruct, EncodeError> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self::SerializeTupleVariant, EncodeError> {
+        Err(unsupported("An enum with data"))
+    }
+
+    fn serialize_map(self, _: Option<usize>) -> Result<Self::SerializeMap, EncodeError> {
+        Err(unsupported("A nested map"))
+    }
+
+    fn serialize_struct(
+        self,
+        name: &'static str,
+        _: usize,
+    ) -> Result<Self::SerializeStruct, EncodeError> {
+        if name != FILE_TOKEN {
+            return Err(unsupported("A nested structure"));
+        }
+        Ok(FileEncoder {
+            field: self.name,
+            parts: self.parts,
+            name: String::new(),
+            file_type: String::new(),
+            data: Bytes::new(),
+        })
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self::SerializeStructVariant, EncodeError> {
+        Err(unsupported("An enum with data"))
+    }
+}
+
+struct ListEncoder<'a> {
+    name: String,
+    parts: &'a mut Vec<Part>,
+}
+
+impl ListEncoder<'_> {
+    fn element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), EncodeError> {
+        value.serialize(PartSerializer {
+            name: self.name.clone(),
+            parts: self.parts,
+            in_list: true,
+        })
+    }
+}
+
+impl ser::SerializeSeq for ListEncoder<'_> {
+    type Ok = ();
+    type Error = EncodeError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), EncodeError> {
+        self.element(value)
+    }
+
+    fn end(self) -> Result<(), EncodeError> {
+        Ok(())
+    }
+}
+
+impl ser::SerializeTuple for ListEncoder<'_> {
+    type Ok = ();
+    type Error = EncodeError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), EncodeError> {
+        self.element(value)
+    }
+
+    fn end(self) -> Result<(), EncodeError> {
+        Ok(())
+    }
+}
+
+impl ser::SerializeTupleStruct for ListEncoder<'_> {
+    type Ok = ();
+    type Error = EncodeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), EncodeError> {
+        self.element(value)
+    }
+
+    fn end(self) -> Result<(), EncodeError> {
+        Ok(())
+    }
+}
+
+/// Collects the fields emitted by `File::serialize` while encoding.
+struct FileEncoder<'a> {
+    field: String,
+    parts: &'a mut Vec<Part>,
+    name: String,
+    file_type: String,
+    data: Bytes,
+}
+
+impl SerializeStruct for FileEncoder<'_> {
+    type Ok = ();
+    type Error = EncodeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), EncodeError> {
+        match key {
+            "name" => self.name = value.serialize(TextSerializer)?,
+            "file_type" => self.file_type = value.serialize(TextSerializer)?,
+            "data" => self.data = value.serialize(BytesSerializer)?,
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn end(self) -> Result<(), EncodeError> {
+        if !self.data.is_empty() {
+            self.parts
+                .push(Part::file(self.field, self.name, self.file_type, self.data));
+        }
+        Ok(())
+    }
+}
+
+/// Renders map keys and file metadata.
+struct TextSerializer;
+
+impl Serializer for TextSerializer {
+    type Ok = String;
+    type Error = EncodeError;
+    type SerializeSeq = Impossible<String, EncodeError>;
+    type SerializeTuple = Impossible<String, EncodeError>;
+    type SerializeTupleStruct = Impossible<String, EncodeError>;
+    type SerializeTupleVariant = Impossible<String, EncodeError>;
+    type SerializeMap = Impossible<String, EncodeError>;
+    type SerializeStruct = Impossible<String, EncodeError>;
+    type SerializeStructVariant = Impossible<String, EncodeError>;
+
+    fn is_human_readable(&self) -> bool {
+        false
+    }
+
+    unsupported_methods! {
+        "A non-text value";
+        serialize_bytes(&[u8]), serialize_none(), serialize_unit(),
+        serialize_unit_struct(&'static str),
+    }
+
+    fn serialize_bool(self, v: bool) -> Result<String, EncodeError> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<String, EncodeError> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<String, EncodeError> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<String, EncodeError> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<String, EncodeError> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<String, EncodeError> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<String, EncodeError> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<String, EncodeError> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<String, EncodeError> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<String, EncodeError> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<String, EncodeError> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_char(self, v: char) -> Result<String, EncodeError> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_str(self, v: &str) -> Result<String, EncodeError> {
+        Ok(v.to_owned())
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<String, EncodeError> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _: &'static str,
+        _: u32,
+        variant: &'static str,
+    ) -> Result<String, EncodeError> {
+        Ok(variant.to_owned())
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _: &'static str,
+        value: &T,
+    ) -> Result<String, EncodeError> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+        _: &T,
+    ) -> Result<String, EncodeError> {
+        Err(unsupported("A non-text value"))
+    }
+
+    fn serialize_seq(self, _: Option<usize>) -> Result<Self::SerializeSeq, EncodeError> {
+        Err(unsupported("A non-text value"))
+    }
+
+    fn serialize_tuple(self, _: usize) -> Result<Self::SerializeTuple, EncodeError> {
+        Err(unsupported("A non-text value"))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self::SerializeTupleStruct, EncodeError> {
+        Err(unsupported("A non-text value"))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self::SerializeTupleVariant, EncodeError> {
+        Err(unsupported("A non-text value"))
+    }
+
+    fn serialize_map(self, _: Option<usize>) -> Result<Self::SerializeMap, EncodeError> {
+        Err(unsupported("A non-text value"))
+    }
+
+    fn serialize_struct(
+        self,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self::SerializeStruct, EncodeError> {
+        Err(unsupported("A non-text value"))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self::SerializeStructVariant, EncodeError> {
+        Err(unsupported("A non-text value"))
+    }
+}
+
+/// Receives the data of a `File`, emitted with `serialize_bytes`.
+struct BytesSerializer;
+
+impl Serializer for BytesSerializer {
+    type Ok = Bytes;
+    type Error = EncodeError;
+    type SerializeSeq = Impossible<Bytes, EncodeError>;
+    type SerializeTuple = Impossible<Bytes, EncodeError>;
+    type SerializeTupleStruct = Impossible<Bytes, EncodeError>;
+    type SerializeTupleVariant = Impossible<Bytes, EncodeError>;
+    type SerializeMap = Impossible<Bytes, EncodeError>;
+    type SerializeStruct = Impossible<Bytes, EncodeError>;
+    type SerializeStructVariant = Impossible<Bytes, EncodeError>;
+
+    fn is_human_readable(&self) -> bool {
+        false
+    }
+
+    unsupported_methods! {
+        "File data other than bytes";
+        serialize_bool(bool), serialize_i8(i8), serialize_i16(i16), serialize_i32(i32),
+        serialize_i64(i64), serialize_u8(u8), serialize_u16(u16), serialize_u32(u32),
+        serialize_u64(u64), serialize_f32(f32), serialize_f64(f64), serialize_char(char),
+        serialize_str(&str), serialize_none(), serialize_unit(),
+        serialize_unit_struct(&'static str),
+        serialize_unit_variant(&'static str, u32, &'static str),
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<Bytes, EncodeError> {
+        Ok(Bytes::copy_from_slice(v))
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<Bytes, EncodeError> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _: &'static str,
+        value: &T,
+    ) -> Result<Bytes, EncodeError> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+        _: &T,
+    ) -> Result<Bytes, EncodeError> {
+        Err(unsupported("File data other than bytes"))
+    }
+
+    fn serialize_seq(self, _: Option<usize>) -> Result<Self::SerializeSeq, EncodeError> {
+        Err(unsupported("File data other than bytes"))
+    }
+
+    fn serialize_tuple(self, _: usize) -> Result<Self::SerializeTuple, EncodeError> {
+        Err(unsupported("File data other than bytes"))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self::SerializeTupleStruct, EncodeError> {
+        Err(unsupported("File data other than bytes"))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self::SerializeTupleVariant, EncodeError> {
+        Err(unsupported("File data other than bytes"))
+    }
+
+    fn serialize_map(self, _: Option<usize>) -> Result<Self::SerializeMap, EncodeError> {
+        Err(unsupported("File data other than bytes"))
+    }
+
+    fn serialize_struct(
+        self,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self::SerializeStruct, EncodeError> {
+        Err(unsupported("File data other than bytes"))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self::SerializeStructVariant, EncodeError> {
+        Err(unsupported("File data other than bytes"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{File, Multipart};
+    use actix_web::FromRequest;
+    use futures::StreamExt;
+    use serde::{Deserialize, Serialize};
+    use std::collections::HashMap;
+
+    #[derive(Serialize, Deserialize)]
+    struct Test {
+        first_param: String,
+        u32_param: u32,
+        optional_param: Option<i32>,
+        string_param: Vec<String>,
+        file_param: File,
+        files_param: Vec<File>,
+    }
+
+    fn create_file(name: &str, data: &[u8]) -> File {
+        File::for_test(name, "application/octet-stream", data)
+    }
+
+    #[actix_rt::test]
+    async fn test_round_trip() {
+        let value = Test {
+            first_param: "A simple test".to_owned(),
+            u32_param: 56,
+            optional_param: None,
+            string_param: vec!["a".to_owned(), "b".to_owned()],
+            file_param: create_file("fn.bin", &[0, 1, 2, 255]),
+            files_param: vec![create_file("a.txt", b"a"), create_file("b.txt", b"b")],
+        };
+
+        let (boundary, body) = to_multipart(&value).unwrap();
+        let body: Vec<Bytes> = body.collect().await;
+        let (req, mut payload) = actix_web::test::TestRequest::post()
+            .insert_header((
+                "content-type",
+                format!("multipart/form-data; boundary={}", boundary),
+            ))
+            .set_payload(body.concat())
+            .to_http_parts();
+
+        match Multipart::<Test>::from_request(&req, &mut payload).await {
+            Ok(data) => {
+                assert_eq!(data.first_param, "A simple test");
+                assert_eq!(data.u32_param, 56);
+                assert!(data.optional_param.is_none());
+                assert_eq!(data.string_param, vec!["a", "b"]);
+                assert_eq!(data.file_param.data(), &vec![0, 1, 2, 255]);
+                assert_eq!(data.file_param.name(), "fn.bin");
+                assert_eq!(data.files_param.len(), 2);
+                assert_eq!(data.files_param[1].data(), b"b");
+            }
+            Err(_) => panic!("Failed to parse multipart into structure"),
+        }
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct Empty {
+        #[serde(default)]
+        title: String,
+        #[serde(default)]
+        tags: Vec<String>,
+        #[serde(default)]
+        files: Vec<File>,
+        file: Option<File>,
+        count: u32,
+    }
+
+    #[actix_rt::test]
+    async fn test_empty_values_round_trip() {
+        let value = Empty {
+            title: String::new(),
+            tags: vec!["a".to_owned(), String::new()],
+            files: Vec::new(),
+            file: Some(create_file("empty.bin", b"")),
+            count: 0,
+        };
+
+        let builder = to_multipart_builder(&value).unwrap();
+        let (req, mut payload) = builder.test_request().unwrap().to_http_parts();
+        let data = Multipart::<Empty>::from_request(&req, &mut payload)
+            .await
+            .unwrap();
+
+        assert_eq!(data.title, "");
+        assert_eq!(data.tags, ["a", ""]);
+        assert!(data.files.is_empty());
+        assert!(data.file.is_none());
+        assert_eq!(data.count, 0);
+    }
+
+    #[actix_rt::test]
+    async fn test_bytes_sent_as_file() {
+        #[derive(Serialize)]
+        struct Blob {
+            blob: RawBytes<'static>,
+        }
+
+        #[derive(Deserialize)]
+        struct Received {
+            blob: File,
+        }
+
+        let builder = to_multipart_builder(&Blob {
+            blob: RawBytes(&[0, 159, 255]),
+        })
+        .unwrap();
+        let (req, mut payload) = builder.test_request().unwrap().to_http_parts();
+        let data = Multipart::<Received>::from_request(&req, &mut payload)
+            .await
+            .unwrap();
+
+        assert_eq!(data.blob.name(), "blob");
+        assert_eq!(data.blob.file_type(), "application/octet-stream");
+        assert_eq!(data.blob.data(), &[0, 159, 255]);
+    }
+
+    #[test]
+    fn test_file_json_is_unaffected() {
+        let json = serde_json::to_value(create_file("fn.bin", b"test")).unwrap();
+        assert!(json.get("data").is_none());
+
+        #[derive(Serialize)]
+        struct Nested {
+            inner: HashMap<String, String>,
+        }
+        assert!(to_multipart(&Nested {
+            inner: HashMap::new()
+        })
+        .is_err());
+    }
+}
@@ -0,0 +1,330 @@
+use crate::digest::Algorithm;
+use crate::reader::ReadError;
+use actix_web::http::StatusCode;
+use actix_web::{HttpResponse, ResponseError};
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// Errors returned when a multipart payload can't be turned into the target structure.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum MultipartError {
+    /// The collected fields don't match the target structure.
+    Deserialize(serde_json::Error),
+    /// The value of a field, or of a path inside a nested field, doesn't match the target
+    /// structure. Also returned when a JSON part can't be parsed.
+    InvalidField {
+        path: String,
+        source: serde_json::Error,
+    },
+    /// The checksum sent by the client for a part doesn't match the received bytes.
+    DigestMismatch { field: String, algorithm: Algorithm },
+    /// A part announces a checksum only with algorithms that aren't enabled.
+    UnsupportedDigest { field: String, algorithm: String },
+    /// A part uses an unsupported encoding, or its data isn't valid for the declared one.
+    InvalidEncoding { field: String, encoding: String },
+    /// A part is larger than the configured limit once decoded.
+    LimitExceeded { field: String, limit: usize },
+    /// A part required to extract the body is missing, e.g. the root of a `multipart/related`
+    /// body.
+    MissingPart { name: String },
+    /// Several parts of a `multipart/related` body have the same `Content-ID`.
+    DuplicatePart { name: String },
+    /// A field is sent both in the body and in the merged path or query parameters.
+    ParamConflict { field: String },
+    /// Parts that the target structure doesn't declare, with `MultipartConfig::strict`.
+    UnknownFields { fields: Vec<String> },
+    /// An error about the value read from a part, with `MultipartConfig::positions`. `index`
+    /// starts at 0 for the first part, and `offset` is the position of its headers in the body.
+    AtPart {
+        index: usize,
+        offset: usize,
+        source: Box<MultipartError>,
+    },
+    /// The request body isn't in one of the accepted formats.
+    UnsupportedContentType(String),
+    /// The body couldn't be read, or isn't a complete multipart body.
+    Read(String),
+    /// A field contains `multipart/mixed` parts nested deeper than the configured limit.
+    NestingTooDeep { field: String, limit: usize },
+    /// The structure was extracted but didn't pass `Validate::validate`.
+    #[cfg(feature = "validator")]
+    Validation(validator::ValidationErrors),
+}
+
+impl MultipartError {
+    /// Errors attached to individual fields, keyed by field path (`parent.child`, `list[1]`).
+    pub fn field_errors(&self) -> BTreeMap<String, Vec<FieldError>> {
+        let mut fields = BTreeMap::new();
+        match self {
+            MultipartError::Deserialize(_) => {}
+            MultipartError::InvalidField { path, source } => {
+                fields.insert(
+                    path.clone(),
+                    vec![FieldError {
+                        code: "invalid".to_owned(),
+                        message: Some(source.to_string()),
+                        params: Map::new(),
+                    }],
+                );
+            }
+            MultipartError::DigestMismatch { field, algorithm } => {
+                let mut params = Map::new();
+                params.insert(
+                    "algorithm".to_owned(),
+                    Value::String(algorithm.name().to_owned()),
+                );
+                fields.insert(
+                    field.replace("[]", ""),
+                    vec![FieldError {
+                        code: "digest_mismatch".to_owned(),
+                        message: None,
+                        params,
+                    }],
+                );
+            }
+            MultipartError::UnsupportedDigest { field, algorithm } => {
+                let mut params = Map::new();
+                params.insert("algorithm".to_owned(), Value::String(algorithm.clone()));
+                fields.insert(
+                    field.replace("[]", ""),
+                    vec![FieldError {
+                        code: "unsupported_digest".to_owned(),
+                        message: None,
+                        params,
+                    }],
+                );
+            }
+            MultipartError::InvalidEncoding { field, encoding } => {
+                let mut params = Map::new();
+                params.insert("encoding".to_owned(), Value::String(encoding.clone()));
+                fields.insert(
+                    field.replace("[]", ""),
+                    vec![FieldError {
+                        code: "invalid_encoding".to_owned(),
+                        message: None,
+                        params,
+                    }],
+                );
+            }
+            MultipartError::LimitExceeded { field, limit } => {
+                let mut params = Map::new();
+                params.insert("limit".to_owned(), Value::from(*limit));
+                fields.insert(
+                    field.replace("[]", ""),
+                    vec![FieldError {
+                        code: "limit_exceeded".to_owned(),
+                        message: None,
+                        params,
+                    }],
+                );
+            }
+            MultipartError::MissingPart { .. }
+            | MultipartError::DuplicatePart { .. }
+            | MultipartError::UnsupportedContentType(_)
+            | MultipartError::Read(_) => {}
+            MultipartError::UnknownFields { fields: names } => {
+                for name in names {
+                    fields.insert(
+                        name.clone(),
+                        vec![FieldError {
+                            code: "unknown".to_owned(),
+                            message: None,
+                            params: Map::new(),
+                        }],
+                    );
+                }
+            }
+            MultipartError::AtPart {
+                index,
+                offset,
+                source,
+            } => {
+                fields = source.field_errors();
+                for error in fields.values_mut().flatten() {
+                    error.params.insert("part".to_owned(), Value::from(*index));
+                    error
+                        .params
+                        .insert("offset".to_owned(), Value::from(*offset));
+                }
+            }
+            MultipartError::ParamConflict { field } => {
+                fields.insert(
+                    field.clone(),
+                    vec![FieldError {
+                        code: "conflict".to_owned(),
+                        message: None,
+                        params: Map::new(),
+                    }],
+                );
+            }
+            MultipartError::NestingTooDeep { field, limit } => {
+                let mut params = Map::new();
+                params.insert("limit".to_owned(), Value::from(*limit));
+                fields.insert(
+                    field.replace("[]", ""),
+                    vec![FieldError {
+                        code: "nesting_too_deep".to_owned(),
+                        message: None,
+                        params,
+                    }],
+                );
+            }
+            #[cfg(feature = "validator")]
+            MultipartError::Validation(errors) => {
+                crate::validation::collect_field_errors(errors, "", &mut fields)
+            }
+        }
+        fields
+    }
+}
+
+/// A single field-level error, as rendered in the JSON body of a rejected request.
+#[derive(Debug, Clone, Serialize)]
+pub struct FieldError {
+    pub code: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    #[serde(skip_serializing_if = "Map::is_empty")]
+    pub params: Map<String, Value>,
+}
+
+#[derive(Serialize)]
+struct ErrorResponse {
+    message: String,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    fields: BTreeMap<String, Vec<FieldError>>,
+}
+
+impl fmt::Display for MultipartError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MultipartError::Deserialize(err) => write!(f, "{}", err),
+            MultipartError::InvalidField { path, source } => {
+                write!(f, "Invalid value for field \"{}\": {}", path, source)
+            }
+            MultipartError::DigestMismatch { field, algorithm } => write!(
+                f,
+                "The {} digest of field \"{}\" does not match the received data",
+                algorithm.name(),
+                field
+            ),
+            MultipartError::UnsupportedDigest { field, algorithm } => write!(
+                f,
+                "The {} digest of field \"{}\" can't be verified",
+                algorithm, field
+            ),
+            MultipartError::InvalidEncoding { field, encoding } => write!(
+                f,
+                "Field \"{}\" could not be decoded as {}",
+                field, encoding
+            ),
+            MultipartError::LimitExceeded { field, limit } => write!(
+                f,
+                "Field \"{}\" is larger than {} bytes once decoded",
+                field, limit
+            ),
+            MultipartError::MissingPart { name } => write!(f, "The \"{}\" part is missing", name),
+            MultipartError::DuplicatePart { name } => {
+                write!(f, "Several parts have the Content-ID \"{}\"", name)
+            }
+            MultipartError::ParamConflict { field } => write!(
+                f,
+                "Field \"{}\" is sent more than once in the body and the request parameters",
+                field
+            ),
+            MultipartError::UnknownFields { fields } => {
+                write!(f, "Unexpected fields: {}", fields.join(", "))
+            }
+            MultipartError::AtPart {
+                index,
+                offset,
+                source,
+            } => write!(f, "Part #{} (byte {}): {}", index, offset, source),
+            MultipartError::UnsupportedContentType(content_type) => {
+                write!(f, "Unsupported content type \"{}\"", content_type)
+            }
+            MultipartError::Read(err) => write!(f, "{}", err),
+            MultipartError::NestingTooDeep { field, limit } => write!(
+                f,
+                "Field \"{}\" contains multipart parts nested more than {} level(s) deep",
+                field, limit
+            ),
+            #[cfg(feature = "validator")]
+            MultipartError::Validation(_) => write!(f, "Validation failed"),
+        }
+    }
+}
+
+impl std::error::Error for MultipartError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            MultipartError::Deserialize(err) => Some(err),
+            MultipartError::InvalidField { source, .. } => Some(source),
+            MultipartError::AtPart { source, .. } => Some(source.as_ref()),
+            #[cfg(feature = "validator")]
+            MultipartError::Validation(errors) => Some(errors),
+            _ => None,
+        }
+    }
+}
+
+impl From<ReadError> for MultipartError {
+    fn from(err: ReadError) -> Self {
+        MultipartError::Read(err.to_string())
+    }
+}
+
+impl From<serde_json::Error> for MultipartError {
+    fn from(err: serde_json::Error) -> Self {
+        MultipartError::Deserialize(err)
+    }
+}
+
+/// Every error is rendered as a JSON `ErrorResponse`: the `message` is the `Display` of the
+/// error and `fields` lists the errors of each field, when it's known.
+impl ResponseError for MultipartError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            MultipartError::UnsupportedContentType(_) => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            MultipartError::LimitExceeded { .. } => StatusCode::PAYLOAD_TOO_LARGE,
+            MultipartError::AtPart { source, .. } => source.status_code(),
+            _ => StatusCode::BAD_REQUEST,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::build(self.status_code()).json(ErrorResponse {
+            message: self.to_string(),
+            fields: self.field_errors(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::body::MessageBody;
+
+    #[test]
+    fn test_error_response_body() {
+        let source = serde::de::Error::custom("expected u32");
+        let err = MultipartError::InvalidField {
+            path: "count".to_owned(),
+            source,
+        };
+
+        let response = err.error_response();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body = response.into_body().try_into_bytes().unwrap();
+        assert_eq!(
+            serde_json::from_slice::<Value>(&body).unwrap(),
+            serde_json::json!({
+                "message": "Invalid value for field \"count\": expected u32",
+                "fields": { "count": [{ "code": "invalid", "message": "expected u32" }] }
+            })
+        );
+    }
+}
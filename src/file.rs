@@ -0,0 +1,511 @@
+use crate::digest::Algorithm;
+use crate::encoder;
+use crate::reader;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use encoding_rs::Encoding;
+use serde::de::{self, Deserializer, MapAccess, SeqAccess, Visitor};
+use serde::ser::{SerializeMap, SerializeStruct};
+use serde::{Deserialize, Serialize, Serializer};
+use serde_json::Value;
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::fmt;
+
+pub type FileData = Vec<u8>;
+
+/// Struct name `File` is serialized and deserialized with, so the encoder and the field
+/// deserializer recognize it whatever the name of the user's types.
+pub(crate) const FILE_TOKEN: &str = "$actix_extract_multipart::File";
+
+pub struct File {
+    pub(crate) file_type: String,
+    pub(crate) name: String,
+    pub(crate) data: FileData,
+    pub(crate) digests: HashMap<Algorithm, Vec<u8>>,
+    pub(crate) raw: Option<FileData>,
+}
+impl File {
+    pub fn file_type(&self) -> &String {
+        &self.file_type
+    }
+    pub fn name(&self) -> &String {
+        &self.name
+    }
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+    pub fn data(&self) -> &FileData {
+        &self.data
+    }
+    /// The bytes as they were sent, before the `Content-Transfer-Encoding` and the
+    /// `Content-Encoding` were decoded. Only set when the part was encoded and
+    /// `MultipartConfig::keep_raw` is enabled.
+    pub fn raw_data(&self) -> Option<&FileData> {
+        self.raw.as_ref()
+    }
+    /// Digest computed while the file was received, if the algorithm was enabled in `MultipartConfig`
+    /// or announced by the client in the part headers.
+    pub fn digest(&self, algorithm: Algorithm) -> Option<&[u8]> {
+        self.digests.get(&algorithm).map(|digest| digest.as_slice())
+    }
+    /// The data decoded as text, with the `charset` of the file type (UTF-8 by default). A byte
+    /// order mark takes precedence over the charset, as in browsers.
+    pub fn text(&self) -> Result<String, TextError> {
+        let charset = reader::parameter(&self.file_type, "charset");
+        let encoding = match &charset {
+            Some(label) => Encoding::for_label(label.as_bytes())
+                .ok_or_else(|| TextError::UnsupportedCharset(label.clone()))?,
+            None => encoding_rs::UTF_8,
+        };
+        let (encoding, bom) = Encoding::for_bom(&self.data).unwrap_or((encoding, 0));
+
+        encoding
+            .decode_without_bom_handling_and_without_replacement(&self.data[bom..])
+            .map(|text| text.into_owned())
+            .ok_or(TextError::InvalidData {
+                charset: encoding.name(),
+            })
+    }
+    /// Serializes the file with the given representation instead of the default one.
+    pub fn serialize_as(&self, representation: FileRepr) -> impl Serialize + '_ {
+        Represented {
+            file: self,
+            representation,
+        }
+    }
+    /// A file as the extractors would have received it, without digests.
+    #[cfg(test)]
+    pub(crate) fn for_test(name: &str, file_type: &str, data: impl Into<FileData>) -> Self {
+        File {
+            file_type: file_type.to_owned(),
+            name: name.to_owned(),
+            data: data.into(),
+            digests: HashMap::new(),
+            raw: None,
+        }
+    }
+}
+
+/// Why the data of a `File` can't be read as text.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum TextError {
+    UnsupportedCharset(String),
+    InvalidData { charset: &'static str },
+}
+
+impl fmt::Display for TextError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TextError::UnsupportedCharset(charset) => {
+                write!(f, "unsupported charset \"{}\"", charset)
+            }
+            TextError::InvalidData { charset } => {
+                write!(f, "the file is not valid {} text", charset)
+            }
+        }
+    }
+}
+
+impl std::error::Error for TextError {}
+
+/// Whether `value` is a file collected from a part, marked by `file_value` with `FILE_TOKEN`.
+pub(crate) fn is_file_value(value: &Value) -> bool {
+    value
+        .as_object()
+        .is_some_and(|object| object.contains_key(FILE_TOKEN))
+}
+
+/// Whether a value decoded from the request contains the `FILE_TOKEN` key, or another key
+/// marking values collected from parts, which would let the client pass an object of its own
+/// as a received file.
+pub(crate) fn contains_file_token(value: &Value) -> bool {
+    let reserved = |key: &String| key.starts_with("$actix_extract_multipart::");
+    match value {
+        Value::Object(object) => {
+            object.keys().any(reserved) || object.values().any(contains_file_token)
+        }
+        Value::Array(values) => values.iter().any(contains_file_token),
+        _ => false,
+    }
+}
+
+/// Reads back a file collected from a part.
+pub(crate) fn from_value(value: Value) -> Result<File, serde_json::Error> {
+    Received::deserialize(value).map(File::from)
+}
+
+/// How a `File` is serialized, e.g. when echoed back in a JSON response.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FileRepr {
+    /// `name`, `file_type`, `size` and the computed `digests` (hex encoded).
+    #[default]
+    Metadata,
+    /// The metadata plus `data`, base64 encoded. It can be deserialized back into a `File`.
+    Base64,
+    /// The metadata plus `"data": null`, keeping the shape of `Base64`.
+    DataOmitted,
+}
+
+impl fmt::Debug for File {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("File")
+            .field("name", &self.name)
+            .field("file_type", &self.file_type)
+            .field("len", &self.len())
+            .field("digests", &HexDigests(&self.digests))
+            .finish_non_exhaustive()
+    }
+}
+
+thread_local! {
+    /// Set while `to_multipart` serializes a value, to get the data of its files.
+    static ENCODING: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Runs `f`, a synchronous serialization by `to_multipart`, with files serialized as
+/// `FileRepr::Base64`.
+pub(crate) fn encoding<R>(f: impl FnOnce() -> R) -> R {
+    struct Reset(bool);
+
+    impl Drop for Reset {
+        fn drop(&mut self) {
+            ENCODING.with(|encoding| encoding.set(self.0));
+        }
+    }
+
+    let _reset = Reset(ENCODING.with(|encoding| encoding.replace(true)));
+    f()
+}
+
+/// Every format gets `FileRepr::Metadata`, except `to_multipart`, which sends the data.
+impl Serialize for File {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let representation = match ENCODING.with(Cell::get) {
+            true => FileRepr::Base64,
+            false => FileRepr::Metadata,
+        };
+        self.serialize_as(representation).serialize(serializer)
+    }
+}
+
+struct Represented<'a> {
+    file: &'a File,
+    representation: FileRepr,
+}
+
+/// Formats that aren't human readable write the data of `FileRepr::Base64` as bytes, in the
+/// struct `to_multipart` sends as a file part.
+impl Serialize for Represented<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let file = self.file;
+        if self.representation == FileRepr::Base64 && !serializer.is_human_readable() {
+            let mut state = serializer.serialize_struct(FILE_TOKEN, 3)?;
+            state.serialize_field("name", &file.name)?;
+            state.serialize_field("file_type", &file.file_type)?;
+            state.serialize_field("data", &encoder::RawBytes(&file.data))?;
+            return state.end();
+        }
+
+        let len = match self.representation {
+            FileRepr::Metadata => 4,
+            FileRepr::Base64 | FileRepr::DataOmitted => 5,
+        };
+
+        let mut state = serializer.serialize_struct("File", len)?;
+        state.serialize_field("name", &file.name)?;
+        state.serialize_field("file_type", &file.file_type)?;
+        state.serialize_field("size", &file.len())?;
+        state.serialize_field("digests", &HexDigests(&file.digests))?;
+        match self.representation {
+            FileRepr::Metadata => {}
+            FileRepr::Base64 => state.serialize_field("data", &BASE64.encode(&file.data))?,
+            FileRepr::DataOmitted => state.serialize_field("data", &None::<String>)?,
+        }
+        state.end()
+    }
+}
+
+struct HexDigests<'a>(&'a HashMap<Algorithm, Vec<u8>>);
+
+impl HexDigests<'_> {
+    fn sorted(&self) -> Vec<(&'static str, String)> {
+        let mut digests: Vec<(&'static str, String)> = self
+            .0
+            .iter()
+            .map(|(algorithm, digest)| {
+                let hex = digest.iter().map(|byte| format!("{:02x}", byte)).collect();
+                (algorithm.name(), hex)
+            })
+            .collect();
+        digests.sort();
+        digests
+    }
+}
+
+impl Serialize for HexDigests<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let digests = self.sorted();
+        let mut map = serializer.serialize_map(Some(digests.len()))?;
+        for (name, hex) in digests {
+            map.serialize_entry(name, &hex)?;
+        }
+        map.end()
+    }
+}
+
+impl fmt::Debug for HexDigests<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.sorted()).finish()
+    }
+}
+
+impl<'de> Deserialize<'de> for File {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_newtype_struct(FILE_TOKEN, FileVisitor)
+    }
+}
+
+struct FileVisitor;
+
+impl<'de> Visitor<'de> for FileVisitor {
+    type Value = File;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a file")
+    }
+
+    fn visit_newtype_struct<D: Deserializer<'de>>(self, deserializer: D) -> Result<File, D::Error> {
+        deserializer.deserialize_any(FileFieldsVisitor)
+    }
+}
+
+/// A map is read as a file serialized by `File` (with `FileRepr::Base64`, or by a compact
+/// format), without its digests: they are only set for files received by the extractors.
+/// Those are passed as a newtype instead, which formats never deserialize a map into.
+struct FileFieldsVisitor;
+
+impl<'de> Visitor<'de> for FileFieldsVisitor {
+    type Value = File;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a file")
+    }
+
+    fn visit_newtype_struct<D: Deserializer<'de>>(self, deserializer: D) -> Result<File, D::Error> {
+        Received::deserialize(deserializer).map(File::from)
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<File, A::Error> {
+        #[derive(Deserialize)]
+        #[serde(field_identifier, rename_all = "snake_case")]
+        enum Field {
+            Name,
+            FileType,
+            Data,
+            #[serde(other)]
+            Other,
+        }
+
+        struct Data(FileData);
+
+        impl<'de> Deserialize<'de> for Data {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                deserialize_data(deserializer).map(Data)
+            }
+        }
+
+        let (mut name, mut file_type, mut data) = (None, None, None);
+        while let Some(field) = map.next_key()? {
+            match field {
+                Field::Name => name = Some(map.next_value()?),
+                Field::FileType => file_type = Some(map.next_value()?),
+                Field::Data => data = Some(map.next_value::<Data>()?.0),
+                Field::Other => {
+                    map.next_value::<de::IgnoredAny>()?;
+                }
+            }
+        }
+
+        Ok(File {
+            name: name.ok_or_else(|| de::Error::missing_field("name"))?,
+            file_type: file_type.ok_or_else(|| de::Error::missing_field("file_type"))?,
+            data: data.ok_or_else(|| de::Error::missing_field("data"))?,
+            digests: HashMap::new(),
+            raw: None,
+        })
+    }
+}
+
+/// A file as collected from a part by `file_value`.
+#[derive(Deserialize)]
+struct Received {
+    file_type: String,
+    name: String,
+    #[serde(deserialize_with = "deserialize_data")]
+    data: FileData,
+    #[serde(default, deserialize_with = "deserialize_digests")]
+    digests: HashMap<Algorithm, Vec<u8>>,
+    #[serde(default, deserialize_with = "deserialize_raw")]
+    raw: Option<FileData>,
+}
+
+impl From<Received> for File {
+    fn from(received: Received) -> Self {
+        File {
+            file_type: received.file_type,
+            name: received.name,
+            data: received.data,
+            digests: received.digests,
+            raw: received.raw,
+        }
+    }
+}
+
+/// The data is received as a base64 string, from the extractor or from a file serialized with
+/// `FileRepr::Base64`, and as a sequence of bytes from other formats.
+fn deserialize_data<'de, D: Deserializer<'de>>(deserializer: D) -> Result<FileData, D::Error> {
+    deserializer.deserialize_any(BytesVisitor {
+        decode: |v| BASE64.decode(v).ok(),
+    })
+}
+
+fn deserialize_raw<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<FileData>, D::Error> {
+    deserialize_data(deserializer).map(Some)
+}
+
+fn deserialize_digests<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<HashMap<Algorithm, Vec<u8>>, D::Error> {
+    struct Digest(Vec<u8>);
+
+    impl<'de> Deserialize<'de> for Digest {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            deserialize_data(deserializer).map(Digest)
+        }
+    }
+
+    let digests = HashMap::<Algorithm, Digest>::deserialize(deserializer)?;
+    Ok(digests
+        .into_iter()
+        .map(|(algorithm, digest)| (algorithm, digest.0))
+        .collect())
+}
+
+/// Accepts bytes as a sequence, as raw bytes, or as a string decoded with `decode`.
+struct BytesVisitor {
+    decode: fn(&str) -> Option<Vec<u8>>,
+}
+
+impl<'de> Visitor<'de> for BytesVisitor {
+    type Value = Vec<u8>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a sequence of bytes or an encoded string")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Vec<u8>, A::Error> {
+        let mut data = Vec::with_capacity(seq.size_hint().unwrap_or_default());
+        while let Some(byte) = seq.next_element::<u8>()? {
+            data.push(byte);
+        }
+        Ok(data)
+    }
+
+    fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<Vec<u8>, E> {
+        Ok(v.to_vec())
+    }
+
+    fn visit_byte_buf<E: de::Error>(self, v: Vec<u8>) -> Result<Vec<u8>, E> {
+        Ok(v)
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Vec<u8>, E> {
+        (self.decode)(v).ok_or_else(|| E::invalid_value(de::Unexpected::Str(v), &self))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "crc32c")]
+    use serde_json::json;
+
+    fn create_file() -> File {
+        #[allow(unused_mut)]
+        let mut file = File::for_test("fn.txt", "text/plain", b"test");
+        #[cfg(feature = "crc32c")]
+        file.digests
+            .insert(Algorithm::Crc32c, vec![0x86, 0xa0, 0x72, 0xc0]);
+        file
+    }
+
+    #[cfg(feature = "crc32c")]
+    #[test]
+    fn test_representations() {
+        let file = create_file();
+
+        assert_eq!(
+            serde_json::to_value(&file).unwrap(),
+            json!({ "name": "fn.txt", "file_type": "text/plain", "size": 4, "digests": { "crc32c": "86a072c0" } })
+        );
+        assert_eq!(
+            serde_json::to_value(file.serialize_as(FileRepr::Base64)).unwrap()["data"],
+            "dGVzdA=="
+        );
+        assert_eq!(
+            serde_json::to_value(file.serialize_as(FileRepr::DataOmitted)).unwrap()["data"],
+            serde_json::Value::Null
+        );
+    }
+
+    #[test]
+    fn test_base64_representation_round_trip() {
+        let file = create_file();
+        let json = serde_json::to_string(&file.serialize_as(FileRepr::Base64)).unwrap();
+        let file: File = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(file.data(), b"test");
+        assert!(file.digests.is_empty());
+        assert!(serde_json::to_value(&file).unwrap().get("data").is_none());
+    }
+
+    #[cfg(feature = "cbor")]
+    #[test]
+    fn test_representations_not_human_readable() {
+        fn round_trip(value: impl Serialize) -> Result<File, String> {
+            let mut data = Vec::new();
+            ciborium::into_writer(&value, &mut data).unwrap();
+            ciborium::from_reader(&data[..]).map_err(|err| err.to_string())
+        }
+        let file = create_file();
+
+        assert!(round_trip(&file).is_err());
+        assert_eq!(
+            round_trip(file.serialize_as(FileRepr::Base64))
+                .unwrap()
+                .data(),
+            b"test"
+        );
+        assert!(round_trip(file.serialize_as(FileRepr::Metadata)).is_err());
+        assert!(round_trip(file.serialize_as(FileRepr::DataOmitted)).is_err());
+    }
+
+    #[test]
+    fn test_debug_does_not_dump_data() {
+        let mut file = create_file();
+        file.data = vec![b'x'; 1 << 20];
+        let debug = format!("{:?}", file);
+
+        assert!(debug.len() < 200);
+        assert!(debug.contains("len: 1048576"));
+        #[cfg(feature = "crc32c")]
+        assert!(debug.contains("\"crc32c\": \"86a072c0\""));
+    }
+}
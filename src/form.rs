@@ -0,0 +1,239 @@
+use crate::file::contains_file_token;
+use crate::format::reserved_key;
+use crate::position::Positions;
+use crate::{
+    deserialize_fields, extract_from_request,
+    params::{merge_params, request_params},
+    params_insert,
+    reader::essence,
+    MultipartConfig, MultipartError,
+};
+use actix_web::http::header;
+use actix_web::web::Bytes;
+use actix_web::{dev::Payload, Error, FromRequest, HttpRequest};
+use futures_util::future::Future;
+use serde_json::{Map, Value};
+use std::ops::{Deref, DerefMut};
+use std::pin::Pin;
+
+/// Extracts `T` from a `multipart/form-data`, `application/x-www-form-urlencoded` or JSON body,
+/// depending on the request `Content-Type`, so one handler can serve browser forms and scripts.
+///
+/// Field names follow the same rules in the three formats: `name[]` fields are collected into
+/// a list, urlencoded values are read like multipart text parts, and path and query parameters
+/// are merged as configured in `MultipartConfig`. The urlencoded and JSON bodies are limited by
+/// actix's `PayloadConfig`, and can't set `File` fields.
+pub struct AnyForm<T> {
+    data: T,
+}
+
+impl<T> AnyForm<T> {
+    pub fn into_inner(self) -> T {
+        self.data
+    }
+}
+
+impl<T> Deref for AnyForm<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.data
+    }
+}
+
+impl<T> DerefMut for AnyForm<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.data
+    }
+}
+
+fn urlencoded_params(body: &[u8]) -> Result<Map<String, Value>, MultipartError> {
+    let pairs = serde_urlencoded::from_bytes::<Vec<(String, String)>>(body)
+        .map_err(|err| MultipartError::Deserialize(serde::de::Error::custom(err)))?;
+
+    let mut params = Map::new();
+    for (name, value) in pairs {
+        if value.is_empty() {
+            continue;
+        }
+        params_insert(
+            &mut params,
+            &name,
+            &name.replace("[]", ""),
+            Value::String(value),
+        );
+    }
+    Ok(params)
+}
+
+fn json_params(body: &[u8]) -> Result<Value, MultipartError> {
+    let value = serde_json::from_slice::<Value>(body)?;
+    if contains_file_token(&value) {
+        return Err(MultipartError::Deserialize(serde::de::Error::custom(
+            reserved_key(),
+        )));
+    }
+    let object = match value {
+        Value::Object(object) => object,
+        value => return Ok(value),
+    };
+
+    let mut params = Map::new();
+    for (name, value) in object {
+        let name_formatted = name.replace("[]", "");
+        match value {
+            Value::Array(values) if name.ends_with("[]") => {
+                for value in values {
+                    params_insert(&mut params, &name, &name_formatted, value);
+                }
+            }
+            value => params_insert(&mut params, &name, &name_formatted, value),
+        }
+    }
+    Ok(Value::Object(params))
+}
+
+impl<T: serde::de::DeserializeOwned + 'static> FromRequest for AnyForm<T> {
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let content_type = req
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .map(essence)
+            .unwrap_or_default();
+
+        if content_type.starts_with("multipart/") {
+            let extraction = extract_from_request::<T>(req, payload);
+            return Box::pin(async move {
+                Ok(AnyForm {
+                    data: extraction.await?,
+                })
+            });
+        }
+
+        let is_json = content_type == "application/json" || content_type.ends_with("+json");
+        if !is_json && content_type != "application/x-www-form-urlencoded" {
+            return Box::pin(async move {
+                Err(MultipartError::UnsupportedContentType(content_type).into())
+            });
+        }
+
+        let config = MultipartConfig::from_req(req);
+        let request_params = request_params(req, &config);
+        let body = Bytes::from_request(req, payload);
+        Box::pin(async move {
+            let body = body.await?;
+            let mut value = match is_json {
+                true => json_params(&body)?,
+                false => Value::Object(urlencoded_params(&body)?),
+            };
+            if let Value::Object(params) = &mut value {
+                merge_params(params, request_params?, config.get_precedence())?;
+            }
+            Ok(AnyForm {
+                data: deserialize_fields(value, &config, &Positions::default(), false)?,
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{File, MultipartBuilder};
+    use actix_web::test::TestRequest;
+    use serde::Deserialize;
+
+    #[allow(dead_code)]
+    #[derive(Deserialize)]
+    struct Test {
+        title: String,
+        count: u32,
+        tags: Vec<String>,
+        file: Option<File>,
+    }
+
+    async fn extract(req: TestRequest) -> Result<Test, Error> {
+        let (req, mut payload) = req.to_http_parts();
+        AnyForm::<Test>::from_request(&req, &mut payload)
+            .await
+            .map(AnyForm::into_inner)
+    }
+
+    #[actix_rt::test]
+    async fn test_same_struct_from_each_content_type() {
+        let multipart = MultipartBuilder::new()
+            .text("title", "A title")
+            .text("count", "2")
+            .text("tags[]", "a")
+            .text("tags[]", "b")
+            .file("file", "fn.txt", "text/plain", "test")
+            .test_request()
+            .unwrap();
+        let urlencoded = TestRequest::post()
+            .insert_header((header::CONTENT_TYPE, "application/x-www-form-urlencoded"))
+            .set_payload("title=A+title&count=2&tags%5B%5D=a&tags[]=b");
+        let json = TestRequest::post()
+            .insert_header((header::CONTENT_TYPE, "application/json"))
+            .set_payload(r#"{"title": "A title", "count": 2, "tags[]": ["a", "b"]}"#);
+
+        let data = extract(multipart).await.unwrap();
+        assert_eq!(data.file.unwrap().data(), b"test");
+
+        for req in [urlencoded, json] {
+            let data = extract(req).await.unwrap();
+            assert_eq!(data.title, "A title");
+            assert_eq!(data.count, 2);
+            assert_eq!(data.tags, ["a", "b"]);
+            assert!(data.file.is_none());
+        }
+    }
+
+    #[actix_rt::test]
+    async fn test_reserved_file_key_rejected() {
+        let req = TestRequest::post()
+            .insert_header((header::CONTENT_TYPE, "application/json"))
+            .set_payload(
+                r#"{"title": "A title", "count": 2, "tags": [], "file": {"name": "fn.txt",
+                "file_type": "text/plain", "data": [116], "digests": {"md5": "00"},
+                "$actix_extract_multipart::File": true}}"#,
+            );
+
+        let err = extract(req).await.err().unwrap();
+        assert!(err.to_string().contains("is reserved"));
+    }
+
+    #[actix_rt::test]
+    async fn test_files_only_from_multipart() {
+        let urlencoded = TestRequest::post()
+            .insert_header((header::CONTENT_TYPE, "application/x-www-form-urlencoded"))
+            .set_payload("title=A+title&count=2&file=test");
+        let json = TestRequest::post()
+            .insert_header((header::CONTENT_TYPE, "application/json"))
+            .set_payload(
+                r#"{"title": "A title", "count": 2, "tags": [], "file": {"name": "fn.txt",
+                "file_type": "text/plain", "data": [116, 101, 115, 116]}}"#,
+            );
+
+        for req in [urlencoded, json] {
+            let err = extract(req).await.err().unwrap();
+            assert!(err.to_string().contains("multipart/form-data"));
+        }
+    }
+
+    #[actix_rt::test]
+    async fn test_unsupported_content_type() {
+        let req = TestRequest::post()
+            .insert_header((header::CONTENT_TYPE, "text/plain"))
+            .set_payload("title");
+
+        let err = extract(req).await.err().unwrap();
+        assert_eq!(
+            err.as_response_error().status_code(),
+            actix_web::http::StatusCode::UNSUPPORTED_MEDIA_TYPE
+        );
+    }
+}
@@ -0,0 +1,187 @@
+use crate::file::{contains_file_token, FILE_TOKEN};
+use serde_json::Value;
+use std::fmt;
+use std::sync::Arc;
+
+/// Error returned by a part format decoder.
+pub type FormatError = Box<dyn std::error::Error + Send + Sync>;
+
+type Decoder = Arc<dyn Fn(&[u8]) -> Result<Value, FormatError> + Send + Sync>;
+
+/// Decoders of structured text parts, keyed by content type. A decoder turns the part into a
+/// value which is then deserialized into the nested field, like a JSON part.
+#[derive(Clone)]
+pub(crate) struct Formats {
+    decoders: Vec<(String, Decoder)>,
+}
+
+impl Default for Formats {
+    fn default() -> Self {
+        let mut formats = Formats {
+            decoders: Vec::new(),
+        };
+        formats.insert("application/json", |data| Ok(serde_json::from_slice(data)?));
+        #[cfg(feature = "cbor")]
+        formats.insert("application/cbor", |data| Ok(ciborium::from_reader(data)?));
+        #[cfg(feature = "msgpack")]
+        for content_type in [
+            "application/msgpack",
+            "application/x-msgpack",
+            "application/vnd.msgpack",
+        ] {
+            formats.insert(content_type, |data| Ok(rmp_serde::from_slice(data)?));
+        }
+        #[cfg(feature = "yaml")]
+        for content_type in ["application/yaml", "application/x-yaml", "text/yaml"] {
+            formats.insert(content_type, |data| Ok(serde_norway::from_slice(data)?));
+        }
+        #[cfg(feature = "toml")]
+        formats.insert("application/toml", |data| {
+            Ok(toml::from_str(std::str::from_utf8(data)?)?)
+        });
+        formats
+    }
+}
+
+impl fmt::Debug for Formats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list()
+            .entries(self.decoders.iter().map(|(content_type, _)| content_type))
+            .finish()
+    }
+}
+
+impl Formats {
+    /// Replaces the decoder of `content_type` if there is one already.
+    pub(crate) fn insert<F>(&mut self, content_type: &str, decoder: F)
+    where
+        F: Fn(&[u8]) -> Result<Value, FormatError> + Send + Sync + 'static,
+    {
+        let content_type = content_type.trim().to_ascii_lowercase();
+        self.decoders
+            .retain(|(existing, _)| *existing != content_type);
+        self.decoders.push((content_type, Arc::new(decoder)));
+    }
+
+    /// Decodes `data` if there is a decoder for `essence` (the content type without parameters).
+    /// Types with a structured syntax suffix, such as `application/ld+json`, fall back to the
+    /// decoder of `application/json`. Values containing the key that marks received files are
+    /// rejected.
+    pub(crate) fn decode(&self, essence: &str, data: &[u8]) -> Option<Result<Value, FormatError>> {
+        let essence = essence.to_ascii_lowercase();
+        let suffixed = essence
+            .split_once('/')
+            .and_then(|(_, subtype)| subtype.rsplit_once('+'))
+            .map(|(_, suffix)| format!("application/{}", suffix));
+
+        [Some(essence), suffixed]
+            .iter()
+            .flatten()
+            .find_map(|content_type| {
+                self.decoders
+                    .iter()
+                    .find(|(existing, _)| existing == content_type)
+            })
+            .map(|(_, decoder)| match decoder(data) {
+                Ok(value) if contains_file_token(&value) => Err(reserved_key()),
+                result => result,
+            })
+    }
+}
+
+pub(crate) fn reserved_key() -> FormatError {
+    format!("the key \"{}\" is reserved", FILE_TOKEN).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_registry() {
+        let mut formats = Formats::default();
+        formats.insert("text/x-key-value", |data| {
+            let text = std::str::from_utf8(data)?;
+            let (key, value) = text.split_once('=').ok_or("missing '='")?;
+            Ok(json!({ key: value }))
+        });
+
+        assert_eq!(
+            formats
+                .decode("application/ld+json", b"[1]")
+                .unwrap()
+                .unwrap(),
+            json!([1])
+        );
+        assert_eq!(
+            formats.decode("Text/X-Key-Value", b"a=b").unwrap().unwrap(),
+            json!({ "a": "b" })
+        );
+        assert!(formats.decode("text/x-key-value", b"ab").unwrap().is_err());
+        assert!(formats.decode("text/plain", b"a=b").is_none());
+        assert!(formats
+            .decode(
+                "application/json",
+                br#"{"file": [{"$actix_extract_multipart::File": true}]}"#
+            )
+            .unwrap()
+            .is_err());
+    }
+
+    #[cfg(feature = "cbor")]
+    #[test]
+    fn test_cbor() {
+        let mut data = Vec::new();
+        ciborium::into_writer(&json!({ "title": "A title", "year": 2024 }), &mut data).unwrap();
+
+        assert_eq!(
+            Formats::default()
+                .decode("application/cbor", &data)
+                .unwrap()
+                .unwrap(),
+            json!({ "title": "A title", "year": 2024 })
+        );
+    }
+
+    #[cfg(feature = "msgpack")]
+    #[test]
+    fn test_msgpack() {
+        let data = rmp_serde::to_vec_named(&json!({ "tags": ["a", "b"] })).unwrap();
+
+        assert_eq!(
+            Formats::default()
+                .decode("application/msgpack", &data)
+                .unwrap()
+                .unwrap(),
+            json!({ "tags": ["a", "b"] })
+        );
+    }
+
+    #[cfg(feature = "yaml")]
+    #[test]
+    fn test_yaml() {
+        assert_eq!(
+            Formats::default()
+                .decode("application/yaml", b"title: A title\ntags: [a]\n")
+                .unwrap()
+                .unwrap(),
+            json!({ "title": "A title", "tags": ["a"] })
+        );
+    }
+
+    #[cfg(feature = "toml")]
+    #[test]
+    fn test_toml() {
+        assert_eq!(
+            Formats::default()
+                .decode(
+                    "application/toml",
+                    b"title = \"A title\"\n[owner]\nid = 7\n"
+                )
+                .unwrap()
+                .unwrap(),
+            json!({ "title": "A title", "owner": { "id": 7 } })
+        );
+    }
+}
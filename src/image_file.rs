@@ -0,0 +1,410 @@
+use crate::File;
+use actix_web::http::StatusCode;
+use actix_web::{web, ResponseError};
+use image::metadata::Orientation;
+use image::{DynamicImage, ImageDecoder, ImageReader, Limits};
+use serde::de::{Deserializer, Error};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fmt;
+use std::io::Cursor;
+use std::marker::PhantomData;
+
+pub use image::ImageFormat;
+
+/// Constraints checked on an `ImageFile`, declared on a type of your own:
+///
+/// ```no_run
+/// # use actix_extract_multipart::{ImageFormat, ImageOptions};
+/// struct Avatar;
+///
+/// impl ImageOptions for Avatar {
+///     const MAX_PIXELS: u64 = 2048 * 2048;
+///     const FORMATS: &'static [ImageFormat] = &[ImageFormat::Png, ImageFormat::Jpeg];
+///     const ASPECT_RATIO: Option<(f64, f64)> = Some((0.9, 1.1));
+/// }
+/// ```
+pub trait ImageOptions {
+    /// Maximum width × height, checked from the image header before anything is decoded.
+    const MAX_PIXELS: u64 = 40_000_000;
+    /// Accepted formats, detected from the data. Empty accepts every format enabled in `image`.
+    const FORMATS: &'static [ImageFormat] = &[];
+    /// Accepted range of width / height.
+    const ASPECT_RATIO: Option<(f64, f64)> = None;
+    /// Re-encodes the image without its metadata (EXIF, including the location, comments,
+    /// color profiles), once rotated as its EXIF orientation says. See `ImageFile::decode`.
+    const SANITIZE: bool = false;
+    /// Bounding boxes of the thumbnails to generate, which keep the aspect ratio of the image.
+    /// See `ImageFile::decode`.
+    const THUMBNAILS: &'static [(u32, u32)] = &[];
+    /// Format of the sanitized image and of the thumbnails, the format of the upload by default.
+    const OUTPUT_FORMAT: Option<ImageFormat> = None;
+
+    /// Transforms the decoded image before it's sanitized and thumbnailed, e.g. to crop it.
+    fn process(image: DynamicImage) -> DynamicImage {
+        image
+    }
+}
+
+/// Bytes per pixel of the largest color type an image can be decoded into, 32-bit float RGBA.
+const MAX_BYTES_PER_PIXEL: u64 = 16;
+
+/// The default options of `ImageFile`: any format, up to 40 megapixels.
+pub struct AnyImage;
+
+impl ImageOptions for AnyImage {}
+
+/// An image file part, checked against the constraints of `O` from its header: oversized
+/// images are rejected before they are decompressed.
+///
+/// The image isn't decoded during the extraction, which would block the worker; call
+/// `ImageFile::decode` on the extracted image to check its data and get the outputs of
+/// `ImageOptions::SANITIZE` and `ImageOptions::THUMBNAILS`.
+pub struct ImageFile<O: ImageOptions = AnyImage> {
+    file: File,
+    width: u32,
+    height: u32,
+    format: ImageFormat,
+    sanitized: Option<File>,
+    thumbnails: Vec<File>,
+    options: PhantomData<fn() -> O>,
+}
+
+impl<O: ImageOptions> ImageFile<O> {
+    pub fn file(&self) -> &File {
+        &self.file
+    }
+
+    pub fn into_file(self) -> File {
+        self.file
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// The format detected from the data, whatever the declared content type.
+    pub fn format(&self) -> ImageFormat {
+        self.format
+    }
+
+    /// The image re-encoded without metadata, when `ImageOptions::SANITIZE` is set, once
+    /// decoded.
+    pub fn sanitized(&self) -> Option<&File> {
+        self.sanitized.as_ref()
+    }
+
+    /// The thumbnails of `ImageOptions::THUMBNAILS` once decoded, in the same order. They are
+    /// named after the upload and their size, e.g. `avatar_64x64.png`.
+    pub fn thumbnails(&self) -> &[File] {
+        &self.thumbnails
+    }
+
+    /// Decodes the image on a blocking thread, checking that its data is valid, with the memory
+    /// of the decoder limited according to its dimensions. Then generates its sanitized copy
+    /// and thumbnails, as `O` says.
+    ///
+    /// ```no_run
+    /// # use actix_extract_multipart::{ImageError, ImageFile, Multipart};
+    /// # use serde::Deserialize;
+    /// #[derive(Deserialize)]
+    /// struct Profile {
+    ///     avatar: ImageFile,
+    /// }
+    ///
+    /// async fn index(payload: Multipart<Profile>) -> Result<String, ImageError> {
+    ///     let avatar = payload.into_inner().avatar.decode().await?;
+    ///     Ok(format!("{} thumbnails", avatar.thumbnails().len()))
+    /// }
+    /// ```
+    pub async fn decode(self) -> Result<Self, ImageError>
+    where
+        O: 'static,
+    {
+        web::block(move || self.decoded())
+            .await
+            .map_err(|_| ImageError::Blocking)?
+            .map_err(ImageError::Invalid)
+    }
+
+    fn check(file: File) -> Result<Self, String> {
+        let reader = || {
+            ImageReader::new(Cursor::new(file.data().as_slice()))
+                .with_guessed_format()
+                .map_err(|err| err.to_string())
+        };
+
+        let format = reader()?
+            .format()
+            .ok_or("the file is not a supported image")?;
+        if !O::FORMATS.is_empty() && !O::FORMATS.contains(&format) {
+            return Err(format!("{} images are not allowed", format_name(format)));
+        }
+
+        let (width, height) = reader()?
+            .into_dimensions()
+            .map_err(|err| format!("the file is not a valid image: {}", err))?;
+        let pixels = u64::from(width) * u64::from(height);
+        if pixels > O::MAX_PIXELS {
+            return Err(format!(
+                "the image is {}x{} pixels, more than {}",
+                width,
+                height,
+                O::MAX_PIXELS
+            ));
+        }
+        if let Some((min, max)) = O::ASPECT_RATIO {
+            let ratio = f64::from(width) / f64::from(height);
+            if !(min..=max).contains(&ratio) {
+                return Err(format!(
+                    "the aspect ratio of the image is {:.2}, not between {} and {}",
+                    ratio, min, max
+                ));
+            }
+        }
+
+        Ok(ImageFile {
+            file,
+            width,
+            height,
+            format,
+            sanitized: None,
+            thumbnails: Vec::new(),
+            options: PhantomData,
+        })
+    }
+
+    fn decoded(mut self) -> Result<Self, String> {
+        let (width, height, format) = (self.width, self.height, self.format);
+        let mut limits = Limits::default();
+        limits.max_image_width = Some(width);
+        limits.max_image_height = Some(height);
+        limits.max_alloc = Some(u64::from(width) * u64::from(height) * MAX_BYTES_PER_PIXEL);
+        let mut reader = ImageReader::with_format(Cursor::new(self.file.data().as_slice()), format);
+        reader.limits(limits);
+        let invalid = |err: image::ImageError| format!("the file is not a valid image: {}", err);
+        let mut decoder = reader.into_decoder().map_err(invalid)?;
+        let orientation = decoder.orientation().unwrap_or(Orientation::NoTransforms);
+        let mut image = DynamicImage::from_decoder(decoder).map_err(invalid)?;
+
+        if O::SANITIZE || !O::THUMBNAILS.is_empty() {
+            image.apply_orientation(orientation);
+            let image = O::process(image);
+            let output = O::OUTPUT_FORMAT
+                .filter(ImageFormat::writing_enabled)
+                .or(Some(format).filter(ImageFormat::writing_enabled))
+                .unwrap_or(ImageFormat::Png);
+
+            if O::SANITIZE {
+                self.sanitized = Some(encode(&image, output, self.file.name.clone())?);
+            }
+            self.thumbnails = O::THUMBNAILS
+                .iter()
+                .map(|&(max_width, max_height)| {
+                    let thumbnail = image.thumbnail(max_width, max_height);
+                    let name = thumbnail_name(&self.file.name, max_width, max_height, output);
+                    encode(&thumbnail, output, name)
+                })
+                .collect::<Result<_, _>>()?;
+        }
+        Ok(self)
+    }
+}
+
+/// Errors returned by `ImageFile::decode`.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum ImageError {
+    /// The image couldn't be decoded or re-encoded.
+    Invalid(String),
+    /// The blocking thread pool couldn't run the decoding.
+    Blocking,
+}
+
+impl fmt::Display for ImageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImageError::Invalid(err) => write!(f, "{}", err),
+            ImageError::Blocking => write!(f, "The image could not be decoded"),
+        }
+    }
+}
+
+impl std::error::Error for ImageError {}
+
+impl ResponseError for ImageError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ImageError::Invalid(_) => StatusCode::BAD_REQUEST,
+            ImageError::Blocking => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+/// Encodes a processed image as a new file. Formats without an alpha channel get the image
+/// without it.
+fn encode(image: &DynamicImage, format: ImageFormat, name: String) -> Result<File, String> {
+    let mut data = Vec::new();
+    let result = match format {
+        ImageFormat::Jpeg => {
+            DynamicImage::ImageRgb8(image.to_rgb8()).write_to(Cursor::new(&mut data), format)
+        }
+        _ => image.write_to(Cursor::new(&mut data), format),
+    };
+    result.map_err(|err| format!("the image could not be re-encoded: {}", err))?;
+
+    Ok(File {
+        file_type: format.to_mime_type().to_owned(),
+        name,
+        data,
+        digests: HashMap::new(),
+        raw: None,
+    })
+}
+
+fn thumbnail_name(name: &str, width: u32, height: u32, format: ImageFormat) -> String {
+    let stem = match name.rsplit_once('.') {
+        Some((stem, _)) if !stem.is_empty() => stem,
+        _ => name,
+    };
+    format!("{}_{}x{}.{}", stem, width, height, format_name(format))
+}
+
+fn format_name(format: ImageFormat) -> &'static str {
+    format.extensions_str().first().copied().unwrap_or("these")
+}
+
+impl<'de, O: ImageOptions> Deserialize<'de> for ImageFile<O> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        ImageFile::check(File::deserialize(deserializer)?).map_err(D::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Multipart, MultipartBuilder};
+    use actix_web::FromRequest;
+
+    struct Banner;
+
+    impl ImageOptions for Banner {
+        const MAX_PIXELS: u64 = 100;
+        const FORMATS: &'static [ImageFormat] = &[ImageFormat::Png];
+        const ASPECT_RATIO: Option<(f64, f64)> = Some((1.5, 3.0));
+    }
+
+    #[allow(dead_code)]
+    #[derive(Deserialize)]
+    struct Upload {
+        banner: ImageFile<Banner>,
+    }
+
+    fn encode(width: u32, height: u32, format: ImageFormat) -> Vec<u8> {
+        let mut data = Vec::new();
+        image::RgbImage::new(width, height)
+            .write_to(&mut Cursor::new(&mut data), format)
+            .unwrap();
+        data
+    }
+
+    async fn extract(data: Vec<u8>) -> Result<Multipart<Upload>, String> {
+        let (req, mut payload) = MultipartBuilder::new()
+            .file("banner", "banner.png", "image/png", data)
+            .test_request()
+            .unwrap()
+            .to_http_parts();
+        Multipart::<Upload>::from_request(&req, &mut payload)
+            .await
+            .map_err(|err| err.to_string())
+    }
+
+    #[actix_rt::test]
+    async fn test_image_constraints() {
+        let data = extract(encode(8, 4, ImageFormat::Png)).await.unwrap();
+        assert_eq!((data.banner.width(), data.banner.height()), (8, 4));
+        assert_eq!(data.banner.format(), ImageFormat::Png);
+        assert_eq!(data.banner.file().name(), "banner.png");
+
+        let err = extract(encode(20, 10, ImageFormat::Png)).await.err();
+        assert!(err
+            .unwrap()
+            .ends_with("the image is 20x10 pixels, more than 100"));
+        let err = extract(encode(4, 4, ImageFormat::Png)).await.err();
+        assert!(err.unwrap().contains("aspect ratio of the image is 1.00"));
+        let err = extract(encode(8, 4, ImageFormat::Gif)).await.err();
+        assert!(err.unwrap().ends_with("gif images are not allowed"));
+
+        let mut truncated = encode(8, 4, ImageFormat::Png);
+        truncated.truncate(truncated.len() - 20);
+        let data = extract(truncated).await.unwrap();
+        let err = data.into_inner().banner.decode().await.err().unwrap();
+        assert!(err.to_string().contains("not a valid image"));
+    }
+
+    struct Avatar;
+
+    impl ImageOptions for Avatar {
+        const SANITIZE: bool = true;
+        const THUMBNAILS: &'static [(u32, u32)] = &[(4, 4), (2, 8)];
+        const OUTPUT_FORMAT: Option<ImageFormat> = Some(ImageFormat::Png);
+
+        fn process(image: DynamicImage) -> DynamicImage {
+            image.crop_imm(0, 0, 8, 8)
+        }
+    }
+
+    #[actix_rt::test]
+    async fn test_image_processing() {
+        let (req, mut payload) = MultipartBuilder::new()
+            .file(
+                "avatar",
+                "me.jpg",
+                "image/jpeg",
+                encode(16, 8, ImageFormat::Jpeg),
+            )
+            .test_request()
+            .unwrap()
+            .to_http_parts();
+        #[allow(dead_code)]
+        #[derive(Deserialize)]
+        struct Profile {
+            avatar: ImageFile<Avatar>,
+        }
+
+        let data = Multipart::<Profile>::from_request(&req, &mut payload)
+            .await
+            .unwrap();
+        assert!(data.avatar.thumbnails().is_empty());
+        let avatar = data.into_inner().avatar.decode().await.unwrap();
+        assert_eq!((avatar.width(), avatar.height()), (16, 8));
+        assert_eq!(avatar.file().name(), "me.jpg");
+
+        let sanitized = avatar.sanitized().unwrap();
+        assert_eq!(sanitized.file_type(), "image/png");
+        let image = image::load_from_memory(sanitized.data()).unwrap();
+        assert_eq!((image.width(), image.height()), (8, 8));
+
+        let thumbnails = avatar.thumbnails();
+        assert_eq!(thumbnails.len(), 2);
+        assert_eq!(thumbnails[0].name(), "me_4x4.png");
+        assert_eq!(thumbnails[1].name(), "me_2x8.png");
+        let image = image::load_from_memory(thumbnails[1].data()).unwrap();
+        assert_eq!((image.width(), image.height()), (2, 2));
+    }
+
+    #[test]
+    fn test_huge_dimensions_rejected_from_header() {
+        // A GIF whose header claims 60000x60000 pixels, without the data to back it.
+        let mut data = encode(8, 4, ImageFormat::Gif);
+        data[6..10].copy_from_slice(&[0x60, 0xea, 0x60, 0xea]);
+        let file = File::for_test("huge.gif", "image/gif", data);
+
+        let err = ImageFile::<AnyImage>::check(file).err().unwrap();
+        assert_eq!(err, "the image is 60000x60000 pixels, more than 40000000");
+    }
+}
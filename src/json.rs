@@ -0,0 +1,78 @@
+use serde::de::{DeserializeOwned, Deserializer, Error};
+use serde::Deserialize;
+use serde_json::Value;
+
+/// Deserializes a field sent as JSON text, for parts without a JSON `Content-Type`:
+///
+/// ```no_run
+/// # use actix_extract_multipart::File;
+/// # use serde::Deserialize;
+/// # #[derive(Deserialize)]
+/// # struct Metadata {}
+/// #[derive(Deserialize)]
+/// struct Upload {
+///     #[serde(deserialize_with = "actix_extract_multipart::deserialize_json")]
+///     metadata: Metadata,
+///     file: File,
+/// }
+/// ```
+///
+/// Errors are prefixed with the path inside the JSON document, e.g. `author.name: invalid type`.
+pub fn deserialize_json<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: DeserializeOwned,
+{
+    let path_error = |err: serde_path_to_error::Error<serde_json::Error>| match err
+        .path()
+        .to_string()
+        .as_str()
+    {
+        "." => D::Error::custom(err.into_inner()),
+        path => D::Error::custom(format!("{}: {}", path, err.into_inner())),
+    };
+
+    match Value::deserialize(deserializer)? {
+        Value::String(text) => {
+            let mut json = serde_json::Deserializer::from_str(&text);
+            let data = serde_path_to_error::deserialize(&mut json).map_err(path_error)?;
+            json.end().map_err(D::Error::custom)?;
+            Ok(data)
+        }
+        value => serde_path_to_error::deserialize(value).map_err(path_error),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[allow(dead_code)]
+    #[derive(Deserialize)]
+    struct Metadata {
+        title: String,
+        tags: Vec<String>,
+    }
+
+    #[derive(Deserialize)]
+    struct Test {
+        #[serde(deserialize_with = "deserialize_json")]
+        metadata: Metadata,
+    }
+
+    #[test]
+    fn test_json_text_field() {
+        let data: Test = serde_json::from_value(serde_json::json!({
+            "metadata": "{\"title\": \"A title\", \"tags\": [\"a\"]}"
+        }))
+        .unwrap();
+        assert_eq!(data.metadata.tags, ["a"]);
+
+        let err = serde_json::from_value::<Test>(serde_json::json!({
+            "metadata": "{\"title\": \"A title\", \"tags\": [\"a\", 2]}"
+        }))
+        .err()
+        .unwrap();
+        assert!(err.to_string().starts_with("tags[1]: invalid type"));
+    }
+}
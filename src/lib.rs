@@ -1,37 +1,104 @@
 #![crate_name = "actix_extract_multipart"]
 
-use actix_multipart;
-use futures::{StreamExt, TryStreamExt};
-use serde::Deserialize;
-use serde_json::{Map, Number, Value};
+mod builder;
+mod byteranges;
+mod compression;
+mod config;
+#[cfg(feature = "csv")]
+mod csv_file;
+#[cfg(any(feature = "chrono", feature = "time"))]
+pub mod datetime;
+mod de;
+mod digest;
+mod encoder;
+mod error;
+mod file;
+mod form;
+mod format;
+#[cfg(feature = "image")]
+mod image_file;
+mod json;
+mod map;
+#[cfg(feature = "utoipa")]
+mod openapi;
+mod params;
+mod position;
+mod reader;
+mod related;
+mod transfer;
+#[cfg(feature = "validator")]
+pub mod validation;
+mod value;
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
 use std::ops::{Deref, DerefMut};
 use std::str;
 
+use actix_web::web::{Bytes, BytesMut};
 use actix_web::{dev::Payload, Error, FromRequest, HttpRequest};
 use futures_util::future::Future;
 use std::pin::Pin;
 
-pub type FileData = Vec<u8>;
+pub use builder::{BuildError, LineEnding, MultipartBuilder, Part};
+pub use byteranges::{parse_byteranges, ByteRangesError};
+pub use config::MultipartConfig;
+#[cfg(feature = "csv")]
+pub use csv_file::CsvFile;
+pub use de::{deserialize_from_str, Delimiter};
+pub use digest::Algorithm;
+pub use encoder::{to_multipart, EncodeError};
+pub use error::{FieldError, MultipartError};
+pub use file::{File, FileData, FileRepr, TextError};
+pub use form::AnyForm;
+pub use format::FormatError;
+#[cfg(feature = "image")]
+pub use image_file::{AnyImage, ImageError, ImageFile, ImageFormat, ImageOptions};
+pub use json::deserialize_json;
+pub use map::{MultipartEntry, MultipartMap};
+#[cfg(feature = "utoipa")]
+pub use openapi::MultipartRequestBody;
+pub use params::Precedence;
+pub use position::Positioned;
+pub use related::Related;
+#[cfg(feature = "validator")]
+pub use validation::ValidatedMultipart;
+pub use value::MultipartValue;
+
+#[doc(hidden)]
+pub mod __private {
+    pub use serde;
+}
 
-#[derive(Debug, Deserialize)]
-pub struct File {
-    file_type: String,
-    name: String,
-    data: FileData,
+use compression::{ContentDecoder, DecompressError};
+use de::FieldDeserializer;
+use digest::{Digester, ExpectedDigests};
+use position::Positions;
+use reader::{MultipartReader, PartHead, ReadError};
+use transfer::TransferDecoder;
+
+/// Bytes are kept as a base64 string in the collected fields: as an array, each byte would
+/// take a `Value` of its own, about 32 times the size of the data.
+fn bytes_to_value(bytes: &[u8]) -> Value {
+    Value::String(BASE64.encode(bytes))
 }
-impl File {
-    pub fn file_type(&self) -> &String {
-        &self.file_type
-    }
-    pub fn name(&self) -> &String {
-        &self.name
-    }
-    pub fn len(&self) -> usize {
-        self.data.len()
-    }
-    pub fn data(&self) -> &FileData {
-        &self.data
+
+fn verify_digests(
+    field_name: &str,
+    expected: &[(Algorithm, Vec<u8>)],
+    computed: &HashMap<Algorithm, Vec<u8>>,
+) -> Result<(), MultipartError> {
+    for (algorithm, digest) in expected {
+        if computed.get(algorithm) != Some(digest) {
+            return Err(MultipartError::DigestMismatch {
+                field: field_name.to_owned(),
+                algorithm: *algorithm,
+            });
+        }
     }
+    Ok(())
 }
 
 fn params_insert(
@@ -51,6 +118,13 @@ fn params_insert(
     }
 }
 
+/// Extracts `T` from a `multipart/form-data` body.
+///
+/// The parts of a `name[]` list keep the order they were sent in, empty text included, but the
+/// order between different fields isn't kept and other empty parts are skipped, so zipping
+/// `caption[]` and `photo[]` lists only pairs them when every photo has a caption. Fields of
+/// type `Positioned<V>` get the index of their part, and `MultipartMap` keeps every part in
+/// order.
 pub struct Multipart<T> {
     data: T,
 }
@@ -59,6 +133,10 @@ impl<T> Multipart<T> {
     fn new(data: T) -> Self {
         Multipart::<T> { data }
     }
+
+    pub fn into_inner(self) -> T {
+        self.data
+    }
 }
 
 impl<T> Deref for Multipart<T> {
@@ -75,142 +153,569 @@ impl<T> DerefMut for Multipart<T> {
     }
 }
 
-async fn extract_multipart<T>(
-    mut payload: actix_multipart::Multipart,
-) -> Result<T, serde_json::Error>
+#[cfg(test)]
+async fn extract_multipart<T>(payload: MultipartReader) -> Result<T, MultipartError>
 where
     T: serde::de::DeserializeOwned,
 {
-    let mut params = Map::new();
+    extract_multipart_with_config::<T>(payload, &MultipartConfig::default()).await
+}
 
-    'mainWhile: while let Ok(Some(mut field)) = payload.try_next().await {
-        let field_name_string = field.content_disposition().get_name().unwrap().to_string();
-        let field_name = field_name_string.as_str();
-        let field_name_formatted = field_name.replace("[]", "");
+/// The bytes of a part, decoded from its `Content-Transfer-Encoding` and `Content-Encoding`.
+struct PartData {
+    data: Vec<u8>,
+    raw: Option<Vec<u8>>,
+}
 
-        if field.content_disposition().get_filename().is_some() {
-            let mut data: Vec<Value> = Vec::new();
-            let file_name = field
-                .content_disposition()
-                .get_filename()
-                .unwrap()
-                .to_string();
-
-            while let Some(chunk) = field.next().await {
-                match chunk {
-                    Ok(d) => {
-                        let chunk_data: FileData = d.to_vec();
-                        data.reserve_exact(chunk_data.len());
-                        for byte in chunk_data {
-                            data.push(Value::Number(Number::from(byte)));
-                        }
-                    }
-                    Err(_) => {
-                        params.insert(field_name_formatted.to_owned(), Value::Null);
-                        continue 'mainWhile;
-                    }
-                }
+/// Reads a part to the end.
+async fn read_part(
+    reader: &mut MultipartReader,
+    head: &PartHead,
+    field_name: &str,
+    digesters: &mut PartDigesters,
+    config: &MultipartConfig,
+    keep_raw: bool,
+) -> Result<PartData, MultipartError> {
+    read_part_with(
+        reader,
+        head,
+        field_name,
+        digesters,
+        config,
+        keep_raw,
+        |_| Ok(()),
+    )
+    .await
+}
+
+/// `read_part`, handing the decoded bytes to `sink` as they're read, which can drain them
+/// from `PartData::data`.
+async fn read_part_with(
+    reader: &mut MultipartReader,
+    head: &PartHead,
+    field_name: &str,
+    digesters: &mut PartDigesters,
+    config: &MultipartConfig,
+    keep_raw: bool,
+    mut sink: impl FnMut(&mut Vec<u8>) -> Result<(), MultipartError>,
+) -> Result<PartData, MultipartError> {
+    let unsupported = |encoding| MultipartError::InvalidEncoding {
+        field: field_name.to_owned(),
+        encoding,
+    };
+    let mut transfer = TransferDecoder::from_headers(head.headers()).map_err(unsupported)?;
+    let limit = config.get_decompressed_limit();
+    let mut content = ContentDecoder::from_headers(head.headers(), limit).map_err(unsupported)?;
+
+    let transfer_encoding = transfer.name();
+    let content_encoding = content.name().to_owned();
+    let invalid_transfer = |_| unsupported(transfer_encoding.to_owned());
+    let invalid_content = |err| match err {
+        DecompressError::Invalid => unsupported(content_encoding.clone()),
+        DecompressError::TooLarge => MultipartError::LimitExceeded {
+            field: field_name.to_owned(),
+            limit,
+        },
+    };
+
+    let mut raw = (keep_raw && !(transfer.is_identity() && content.is_identity())).then(Vec::new);
+    let mut decoded = Vec::new();
+    let mut data: Vec<u8> = Vec::new();
+
+    while let Some(d) = reader.chunk().await? {
+        if let Some(raw) = raw.as_mut() {
+            raw.extend_from_slice(&d);
+        }
+        decoded.clear();
+        transfer
+            .decode(&d, &mut decoded)
+            .map_err(invalid_transfer)?;
+        digesters.representation.update(&decoded);
+        let start = data.len();
+        content
+            .decode(&decoded, &mut data)
+            .map_err(invalid_content)?;
+        digesters.content.update(&data[start..]);
+        sink(&mut data)?;
+    }
+    decoded.clear();
+    transfer.finish(&mut decoded).map_err(invalid_transfer)?;
+    digesters.representation.update(&decoded);
+    let start = data.len();
+    content
+        .decode(&decoded, &mut data)
+        .map_err(invalid_content)?;
+    content.finish(&mut data).map_err(invalid_content)?;
+    digesters.content.update(&data[start..]);
+    sink(&mut data)?;
+
+    Ok(PartData { data, raw })
+}
+
+/// Reads the body of a nested multipart part as it was sent, up to `limit` bytes.
+async fn read_body(reader: &mut MultipartReader, limit: usize) -> Result<Bytes, ReadError> {
+    let mut body = BytesMut::new();
+    while let Some(chunk) = reader.chunk().await? {
+        if body.len() + chunk.len() > limit {
+            return Err(ReadError::TooLarge(limit));
+        }
+        body.extend_from_slice(&chunk);
+    }
+    Ok(body.freeze())
+}
+
+/// A nested part larger than the limit is reported like other oversized parts.
+fn read_error(err: ReadError, field_name: &str) -> MultipartError {
+    match err {
+        ReadError::TooLarge(limit) => MultipartError::LimitExceeded {
+            field: field_name.to_owned(),
+            limit,
+        },
+        err => err.into(),
+    }
+}
+
+/// Digests computed while a part is read: over the decoded bytes, and over the bytes before
+/// decompression for `Repr-Digest`.
+struct PartDigesters {
+    content: Digester,
+    representation: Digester,
+}
+
+impl PartDigesters {
+    /// For a file, the content digests are the configured ones and the ones sent by the client.
+    fn new(config: Option<&MultipartConfig>, expected: &ExpectedDigests) -> Self {
+        let configured = config.map(|config| config.digests().iter().copied());
+        PartDigesters {
+            content: Digester::new(
+                configured
+                    .into_iter()
+                    .flatten()
+                    .chain(expected.content_algorithms()),
+            ),
+            representation: expected.representation_digester(),
+        }
+    }
+
+    /// Checks the digests sent by the client, and returns the content digests.
+    fn verify(
+        self,
+        field_name: &str,
+        expected: &ExpectedDigests,
+    ) -> Result<HashMap<Algorithm, Vec<u8>>, MultipartError> {
+        let digests = self.content.finalize();
+        verify_digests(field_name, &expected.content, &digests)?;
+        verify_digests(
+            field_name,
+            &expected.representation,
+            &self.representation.finalize(),
+        )?;
+        Ok(digests)
+    }
+}
+
+/// Reads the digests announced by a part.
+fn expected_digests(head: &PartHead, field_name: &str) -> Result<ExpectedDigests, MultipartError> {
+    ExpectedDigests::from_headers(head.headers()).map_err(|algorithm| {
+        MultipartError::UnsupportedDigest {
+            field: field_name.to_owned(),
+            algorithm,
+        }
+    })
+}
+
+fn file_value(
+    file_name: &str,
+    file_type: &str,
+    part: PartData,
+    digests: &HashMap<Algorithm, Vec<u8>>,
+) -> Value {
+    let mut sub_params = Map::new();
+    sub_params.insert(file::FILE_TOKEN.to_owned(), Value::Bool(true));
+    sub_params.insert("file_type".to_owned(), Value::String(file_type.to_owned()));
+    sub_params.insert("name".to_owned(), Value::String(file_name.to_owned()));
+    sub_params.insert("data".to_owned(), bytes_to_value(&part.data));
+    sub_params.insert(
+        "digests".to_owned(),
+        Value::Object(
+            digests
+                .iter()
+                .map(|(algorithm, digest)| (algorithm.name().to_owned(), bytes_to_value(digest)))
+                .collect(),
+        ),
+    );
+    if let Some(raw) = part.raw {
+        sub_params.insert("raw".to_owned(), bytes_to_value(&raw));
+    }
+    Value::Object(sub_params)
+}
+
+/// Files sent under one field as a nested `multipart/mixed` part (RFC 2388), which can
+/// themselves be nested up to `MultipartConfig::max_nesting`.
+fn read_nested_files<'a>(
+    body: Bytes,
+    boundary: String,
+    field_name: &'a str,
+    config: &'a MultipartConfig,
+    depth: usize,
+) -> Pin<Box<dyn Future<Output = Result<Vec<Value>, MultipartError>> + 'a>> {
+    Box::pin(async move {
+        if depth > config.get_max_nesting() {
+            return Err(MultipartError::NestingTooDeep {
+                field: field_name.to_owned(),
+                limit: config.get_max_nesting(),
+            });
+        }
+        let malformed = |err| match err {
+            MultipartError::Read(_) => MultipartError::InvalidEncoding {
+                field: field_name.to_owned(),
+                encoding: "multipart".to_owned(),
+            },
+            err => err,
+        };
+
+        let limit = config.get_nested_limit();
+        let mut reader = MultipartReader::from_bytes(&boundary, body);
+        let mut files = Vec::new();
+        while let Some(head) = reader
+            .next_part()
+            .await
+            .map_err(|err| malformed(err.into()))?
+        {
+            if let Some(boundary) = head.boundary() {
+                let body = read_body(&mut reader, limit)
+                    .await
+                    .map_err(|err| malformed(read_error(err, field_name)))?;
+                files.extend(
+                    read_nested_files(body, boundary, field_name, config, depth + 1).await?,
+                );
+                continue;
             }
-            if data.is_empty() {
-                continue 'mainWhile;
+
+            let expected = expected_digests(&head, field_name)?;
+            let mut digesters = PartDigesters::new(Some(config), &expected);
+            let part = read_part(
+                &mut reader,
+                &head,
+                field_name,
+                &mut digesters,
+                config,
+                config.keeps_raw(),
+            )
+            .await
+            .map_err(malformed)?;
+            let digests = digesters.verify(field_name, &expected)?;
+            if part.data.is_empty() {
+                continue;
             }
 
-            let file_type_str: String = field.content_type().to_string();
+            files.push(file_value(
+                head.filename().unwrap_or_default(),
+                head.content_type(),
+                part,
+                &digests,
+            ));
+        }
+        Ok(files)
+    })
+}
+
+#[cfg(test)]
+async fn extract_multipart_with_config<T>(
+    payload: MultipartReader,
+    config: &MultipartConfig,
+) -> Result<T, MultipartError>
+where
+    T: serde::de::DeserializeOwned,
+{
+    let mut positions = Positions::default();
+    let params = collect_params(payload, config, &csv_fields::<T>(), &mut positions).await?;
+    deserialize_value(Value::Object(params), config, &positions)
+        .map_err(|err| positions.locate(err, config))
+}
 
-            let mut sub_params = Map::new();
-            sub_params.insert("file_type".to_owned(), Value::String(file_type_str));
-            sub_params.insert("name".to_owned(), Value::String(file_name));
-            sub_params.insert("data".to_owned(), Value::Array(data));
+/// The value read from one part, before it's added to the collected fields.
+enum FieldValue {
+    Text(String),
+    File(Value),
+    /// Decoded by a registered format.
+    Structured(Value),
+    /// Files of a nested `multipart/mixed` part.
+    Nested(Vec<Value>),
+    /// Empty files and nested parts, and text that isn't valid UTF-8.
+    Skipped,
+}
 
-            params_insert(
-                &mut params,
-                field_name,
-                &field_name_formatted,
-                Value::Object(sub_params),
-            );
-        } else {
-            let mut data: Vec<u8> = Vec::new();
-
-            while let Some(chunk) = field.next().await {
-                match chunk {
-                    Ok(d) => {
-                        let chunk_data: FileData = d.to_vec();
-                        data.reserve_exact(chunk_data.len());
-                        for byte in chunk_data {
-                            data.push(byte);
-                        }
-                    }
-                    Err(_) => {
-                        params.insert(field_name_formatted.to_owned(), Value::Null);
-                        continue 'mainWhile;
+/// `csv_rows` is the `MAX_ROWS` of a `CsvFile` field, whose part is parsed as it's read.
+#[cfg_attr(not(feature = "csv"), allow(unused_variables))]
+async fn read_field(
+    payload: &mut MultipartReader,
+    head: &PartHead,
+    field_name: &str,
+    csv_rows: Option<usize>,
+    config: &MultipartConfig,
+) -> Result<FieldValue, MultipartError> {
+    let expected = expected_digests(head, field_name)?;
+
+    #[cfg(feature = "csv")]
+    if let (Some(max_rows), None) = (csv_rows, head.boundary()) {
+        return read_csv(payload, head, field_name, max_rows, config, &expected).await;
+    }
+
+    if let Some(boundary) = head.boundary() {
+        let body = read_body(payload, config.get_nested_limit())
+            .await
+            .map_err(|err| read_error(err, field_name))?;
+        let files = read_nested_files(body, boundary, field_name, config, 1).await?;
+        if files.is_empty() {
+            return Ok(FieldValue::Skipped);
+        }
+        Ok(FieldValue::Nested(files))
+    } else if let Some(file_name) = head.filename() {
+        let mut digesters = PartDigesters::new(Some(config), &expected);
+
+        let part = read_part(
+            payload,
+            head,
+            field_name,
+            &mut digesters,
+            config,
+            config.keeps_raw(),
+        )
+        .await?;
+        let digests = digesters.verify(field_name, &expected)?;
+        if part.data.is_empty() {
+            return Ok(FieldValue::Skipped);
+        }
+
+        Ok(FieldValue::File(file_value(
+            file_name,
+            head.content_type(),
+            part,
+            &digests,
+        )))
+    } else {
+        let mut digesters = PartDigesters::new(None, &expected);
+
+        let data = read_part(payload, head, field_name, &mut digesters, config, false)
+            .await?
+            .data;
+
+        digesters.verify(field_name, &expected)?;
+        if data.is_empty() {
+            return Ok(FieldValue::Text(String::new()));
+        }
+
+        if let Some(value) = config.formats().decode(&head.essence(), &data) {
+            let value = value.map_err(|err| MultipartError::InvalidField {
+                path: field_name.replace("[]", ""),
+                source: serde::de::Error::custom(err),
+            })?;
+            return Ok(FieldValue::Structured(value));
+        }
+
+        match String::from_utf8(data.to_vec()) {
+            Ok(text) => Ok(FieldValue::Text(text)),
+            Err(_) => Ok(FieldValue::Skipped),
+        }
+    }
+}
+
+/// Parses a `CsvFile` part into its records as it's read, see `csv_file::CsvRecords`.
+#[cfg(feature = "csv")]
+async fn read_csv(
+    payload: &mut MultipartReader,
+    head: &PartHead,
+    field_name: &str,
+    max_rows: usize,
+    config: &MultipartConfig,
+    expected: &ExpectedDigests,
+) -> Result<FieldValue, MultipartError> {
+    let invalid = |err: String| MultipartError::InvalidField {
+        path: field_name.replace("[]", ""),
+        source: serde::de::Error::custom(err),
+    };
+    let mut records = csv_file::CsvRecords::new(max_rows);
+    let mut digesters = PartDigesters::new(Some(config), expected);
+    read_part_with(
+        payload,
+        head,
+        field_name,
+        &mut digesters,
+        config,
+        false,
+        |data| {
+            let written = records.write(data).map_err(invalid);
+            data.clear();
+            written
+        },
+    )
+    .await?;
+    digesters.verify(field_name, expected)?;
+    if records.is_empty() {
+        return Ok(FieldValue::Skipped);
+    }
+
+    records.finish().map_err(invalid)?;
+    Ok(FieldValue::File(
+        records.into_value(head.filename().unwrap_or_default()),
+    ))
+}
+
+/// Fields of `T` read as a `CsvFile`, with their `MAX_ROWS`.
+#[cfg(feature = "csv")]
+fn csv_fields<T: serde::de::DeserializeOwned>() -> HashMap<String, usize> {
+    csv_file::csv_fields::<T>()
+}
+
+#[cfg(not(feature = "csv"))]
+#[allow(clippy::extra_unused_type_parameters)]
+fn csv_fields<T>() -> HashMap<String, usize> {
+    HashMap::new()
+}
+
+async fn collect_params(
+    mut payload: MultipartReader,
+    config: &MultipartConfig,
+    csv_fields: &HashMap<String, usize>,
+    positions: &mut Positions,
+) -> Result<Map<String, Value>, MultipartError> {
+    let mut params = Map::new();
+
+    while let Some(head) = payload.next_part().await? {
+        let field_name = match head.name() {
+            Some(name) => name.to_string(),
+            None => continue,
+        };
+        let field_name_formatted = field_name.replace("[]", "");
+
+        let csv_rows = csv_fields.get(&field_name_formatted).copied();
+        let value = match read_field(&mut payload, &head, &field_name, csv_rows, config)
+            .await
+            .map_err(|err| position::at_part(err, &head, config))?
+        {
+            FieldValue::Text(text) if text.is_empty() && !field_name.ends_with("[]") => continue,
+            FieldValue::Text(text) => Value::String(text),
+            FieldValue::File(value) | FieldValue::Structured(value) => value,
+            FieldValue::Nested(files) => {
+                if let Value::Array(list) = params
+                    .entry(field_name_formatted.clone())
+                    .or_insert_with(|| Value::Array(Vec::new()))
+                {
+                    for index in list.len()..list.len() + files.len() {
+                        positions.record(format!("{}[{}]", field_name_formatted, index), &head);
                     }
+                    list.extend(files);
                 }
+                continue;
             }
+            FieldValue::Skipped => continue,
+        };
+        params_insert(&mut params, &field_name, &field_name_formatted, value);
+        match &params[&field_name_formatted] {
+            Value::Array(list) if field_name.ends_with("[]") => positions.record(
+                format!("{}[{}]", field_name_formatted, list.len() - 1),
+                &head,
+            ),
+            _ => positions.record(field_name_formatted, &head),
+        }
+    }
 
-            if data.is_empty() {
-                continue 'mainWhile;
-            }
+    Ok(params)
+}
 
-            if let Ok(convert_str) = str::from_utf8(&data) {
-                match convert_str.parse::<isize>() {
-                    Ok(number) => params_insert(
-                        &mut params,
-                        field_name,
-                        &field_name_formatted,
-                        Value::Number(Number::from(number)),
-                    ),
-                    Err(_) => match convert_str {
-                        "true" => params_insert(
-                            &mut params,
-                            field_name,
-                            &field_name_formatted,
-                            Value::Bool(true),
-                        ),
-                        "false" => params_insert(
-                            &mut params,
-                            field_name,
-                            &field_name_formatted,
-                            Value::Bool(false),
-                        ),
-                        _ => params_insert(
-                            &mut params,
-                            field_name,
-                            &field_name_formatted,
-                            Value::String(convert_str.to_owned()),
-                        ),
-                    },
-                }
-            }
-            continue 'mainWhile;
+/// Errors inside nested fields are reported with their path, e.g. `metadata.tags[1]`.
+/// `Positioned` targets are read from `positions`.
+fn deserialize_value<T>(
+    value: Value,
+    config: &MultipartConfig,
+    positions: &Positions,
+) -> Result<T, MultipartError>
+where
+    T: serde::de::DeserializeOwned,
+{
+    deserialize_fields(value, config, positions, true)
+}
+
+/// `deserialize_value`, with `File` targets rejected unless `files` is set.
+fn deserialize_fields<T>(
+    value: Value,
+    config: &MultipartConfig,
+    positions: &Positions,
+    files: bool,
+) -> Result<T, MultipartError>
+where
+    T: serde::de::DeserializeOwned,
+{
+    let mut unknown = Vec::new();
+    let mut callback = |path: serde_ignored::Path| unknown.push(path.to_string());
+    let deserializer = serde_ignored::Deserializer::new(
+        FieldDeserializer::new(value, config.delimiters(), positions, files),
+        &mut callback,
+    );
+    let data = serde_path_to_error::deserialize(deserializer).map_err(|err| {
+        match err.path().to_string() {
+            path if path == "." => MultipartError::Deserialize(err.into_inner()),
+            path => MultipartError::InvalidField {
+                path,
+                source: err.into_inner(),
+            },
         }
+    })?;
+
+    if config.is_strict() && !unknown.is_empty() {
+        return Err(MultipartError::UnknownFields { fields: unknown });
     }
+    Ok(data)
+}
 
-    serde_json::from_value::<T>(Value::Object(params))
+fn extract_from_request<T>(
+    req: &HttpRequest,
+    payload: &mut Payload,
+) -> impl Future<Output = Result<T, MultipartError>>
+where
+    T: serde::de::DeserializeOwned,
+{
+    let multipart = MultipartReader::new(req.headers(), payload.take());
+    let config = MultipartConfig::from_req(req);
+    let request_params = params::request_params(req, &config);
+
+    async move {
+        let mut positions = Positions::default();
+        let mut params =
+            collect_params(multipart, &config, &csv_fields::<T>(), &mut positions).await?;
+        params::merge_params(&mut params, request_params?, config.get_precedence())?;
+        deserialize_value(Value::Object(params), &config, &positions)
+            .map_err(|err| positions.locate(err, &config))
+    }
 }
 
-impl<T: serde::de::DeserializeOwned> FromRequest for Multipart<T> {
+impl<T: serde::de::DeserializeOwned + 'static> FromRequest for Multipart<T> {
     type Error = Error;
     type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
 
     fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
-        let multipart = actix_multipart::Multipart::new(req.headers(), payload.take());
+        let extraction = extract_from_request::<T>(req, payload);
 
         Box::pin(async move {
-            match extract_multipart::<T>(multipart).await {
+            match extraction.await {
                 Ok(response) => Ok(Multipart::<T>::new(response)),
-                Err(_) => Err(actix_web::error::ErrorBadRequest("")),
+                Err(err) => Err(err.into()),
             }
         })
     }
 }
 
 #[cfg(test)]
+#[allow(clippy::bool_assert_comparison, clippy::needless_bool)]
 mod tests {
     use super::*;
-    use actix_multipart;
     use actix_web::error::PayloadError;
-    use actix_web::http::header::{self, HeaderMap};
+    use actix_web::http::header::HeaderMap;
+    use actix_web::http::StatusCode;
     use actix_web::web::Bytes;
+    use actix_web::ResponseError;
+    use futures::StreamExt;
     use futures_core::stream::Stream;
     use serde::Deserialize;
     use tokio::sync::mpsc;
@@ -227,211 +732,120 @@ mod tests {
             UnboundedReceiverStream::new(rx).map(|res| res.map_err(|_| panic!())),
         )
     }
+    const BOUNDARY: &str = "abbc761f78ff4d7cb7573b5a23f96ef0";
+
     fn create_simple_request_with_header() -> (Bytes, HeaderMap) {
-        let bytes = Bytes::from(
-            "testasdadsad\r\n\
-             --abbc761f78ff4d7cb7573b5a23f96ef0\r\n\
-             Content-Disposition: form-data; name=\"file_param\"; filename=\"fn.txt\"\r\n\
-             Content-Type: text/plain; charset=utf-8\r\n\r\n\
-             test\r\n\
-             --abbc761f78ff4d7cb7573b5a23f96ef0\r\n\
-             Content-Disposition: form-data; name=\"u32_param\"\r\n\r\n\
-             56\r\n\
-             --abbc761f78ff4d7cb7573b5a23f96ef0\r\n\
-             Content-Disposition: form-data; name=\"i32_param\"\r\n\r\n\
-             -12\r\n\
-             --abbc761f78ff4d7cb7573b5a23f96ef0\r\n\
-             Content-Disposition: form-data; name=\"first_param\"\r\n\r\n\
-             A simple test\r\n\
-             --abbc761f78ff4d7cb7573b5a23f96ef0--\r\n",
-        );
-        let mut headers = HeaderMap::new();
-        headers.insert(
-            header::CONTENT_TYPE,
-            header::HeaderValue::from_static(
-                "multipart/mixed; boundary=\"abbc761f78ff4d7cb7573b5a23f96ef0\"",
-            ),
-        );
-        (bytes, headers)
+        MultipartBuilder::new()
+            .boundary(BOUNDARY)
+            .file("file_param", "fn.txt", "text/plain; charset=utf-8", "test")
+            .text("u32_param", "56")
+            .text("i32_param", "-12")
+            .text("first_param", "A simple test")
+            .build()
+            .unwrap()
     }
     fn create_simple_request_with_array_header() -> (Bytes, HeaderMap) {
-        let bytes = Bytes::from(
-            "testasdadsad\r\n\
-             --abbc761f78ff4d7cb7573b5a23f96ef0\r\n\
-             Content-Disposition: form-data; name=\"u32_param[]\"\r\n\r\n\
-             56\r\n\
-             --abbc761f78ff4d7cb7573b5a23f96ef0\r\n\
-             Content-Disposition: form-data; name=\"u32_param[]\"\r\n\r\n\
-             49\r\n\
-             --abbc761f78ff4d7cb7573b5a23f96ef0\r\n\
-             Content-Disposition: form-data; name=\"i32_param[]\"\r\n\r\n\
-             -12\r\n\
-             --abbc761f78ff4d7cb7573b5a23f96ef0\r\n\
-             Content-Disposition: form-data; name=\"i32_param[]\"\r\n\r\n\
-             -2\r\n\
-             --abbc761f78ff4d7cb7573b5a23f96ef0\r\n\
-             Content-Disposition: form-data; name=\"i32_param[]\"\r\n\r\n\
-             -17\r\n\
-             --abbc761f78ff4d7cb7573b5a23f96ef0\r\n\
-             Content-Disposition: form-data; name=\"string_param[]\"\r\n\r\n\
-             A simple test\r\n\
-             --abbc761f78ff4d7cb7573b5a23f96ef0\r\n\
-             Content-Disposition: form-data; name=\"string_param[]\"\r\n\r\n\
-             A simple test2\r\n\
-             --abbc761f78ff4d7cb7573b5a23f96ef0\r\n\
-             Content-Disposition: form-data; name=\"string_param[]\"\r\n\r\n\
-             A simple test3\r\n\
-             --abbc761f78ff4d7cb7573b5a23f96ef0--\r\n",
-        );
-        let mut headers = HeaderMap::new();
-        headers.insert(
-            header::CONTENT_TYPE,
-            header::HeaderValue::from_static(
-                "multipart/mixed; boundary=\"abbc761f78ff4d7cb7573b5a23f96ef0\"",
-            ),
-        );
-        (bytes, headers)
+        MultipartBuilder::new()
+            .boundary(BOUNDARY)
+            .text("u32_param[]", "56")
+            .text("u32_param[]", "49")
+            .text("i32_param[]", "-12")
+            .text("i32_param[]", "-2")
+            .text("i32_param[]", "-17")
+            .text("string_param[]", "A simple test")
+            .text("string_param[]", "A simple test2")
+            .text("string_param[]", "A simple test3")
+            .build()
+            .unwrap()
     }
     fn create_simple_request_with_3_files_array_header() -> (Bytes, HeaderMap) {
-        let bytes = Bytes::from(
-            "testasdadsad\r\n\
-             --abbc761f78ff4d7cb7573b5a23f96ef0\r\n\
-             Content-Disposition: form-data; name=\"files_param[]\"; filename=\"fn.txt\"\r\n\
-             Content-Type: text/plain; charset=utf-8\r\n\r\n\
-             test\r\n\
-             --abbc761f78ff4d7cb7573b5a23f96ef0\r\n\
-             Content-Disposition: form-data; name=\"files_param[]\"; filename=\"fn2.txt\"\r\n\
-             Content-Type: text/plain; charset=utf-8\r\n\r\n\
-             test\r\n\
-             --abbc761f78ff4d7cb7573b5a23f96ef0\r\n\
-             Content-Disposition: form-data; name=\"files_param[]\"; filename=\"fn3.txt\"\r\n\
-             Content-Type: text/plain; charset=utf-8\r\n\r\n\
-             test\r\n\
-             --abbc761f78ff4d7cb7573b5a23f96ef0--\r\n",
-        );
-        let mut headers = HeaderMap::new();
-        headers.insert(
-            header::CONTENT_TYPE,
-            header::HeaderValue::from_static(
-                "multipart/mixed; boundary=\"abbc761f78ff4d7cb7573b5a23f96ef0\"",
-            ),
-        );
-        (bytes, headers)
+        MultipartBuilder::new()
+            .boundary(BOUNDARY)
+            .file(
+                "files_param[]",
+                "fn.txt",
+                "text/plain; charset=utf-8",
+                "test",
+            )
+            .file(
+                "files_param[]",
+                "fn2.txt",
+                "text/plain; charset=utf-8",
+                "test",
+            )
+            .file(
+                "files_param[]",
+                "fn3.txt",
+                "text/plain; charset=utf-8",
+                "test",
+            )
+            .build()
+            .unwrap()
     }
     fn create_simple_request_with_1_files_array_header() -> (Bytes, HeaderMap) {
-        let bytes = Bytes::from(
-            "testasdadsad\r\n\
-             --abbc761f78ff4d7cb7573b5a23f96ef0\r\n\
-             Content-Disposition: form-data; name=\"files_param[]\"; filename=\"fn.txt\"\r\n\
-             Content-Type: text/plain; charset=utf-8\r\n\r\n\
-             test\r\n\
-             --abbc761f78ff4d7cb7573b5a23f96ef0--\r\n",
-        );
-        let mut headers = HeaderMap::new();
-        headers.insert(
-            header::CONTENT_TYPE,
-            header::HeaderValue::from_static(
-                "multipart/mixed; boundary=\"abbc761f78ff4d7cb7573b5a23f96ef0\"",
-            ),
-        );
-        (bytes, headers)
+        MultipartBuilder::new()
+            .boundary(BOUNDARY)
+            .file(
+                "files_param[]",
+                "fn.txt",
+                "text/plain; charset=utf-8",
+                "test",
+            )
+            .build()
+            .unwrap()
     }
     fn create_simple_request_with_3_files_array_with_name_without_hooks_header(
     ) -> (Bytes, HeaderMap) {
-        let bytes = Bytes::from(
-            "testasdadsad\r\n\
-             --abbc761f78ff4d7cb7573b5a23f96ef0\r\n\
-             Content-Disposition: form-data; name=\"files_param\"; filename=\"fn.txt\"\r\n\
-             Content-Type: text/plain; charset=utf-8\r\n\r\n\
-             test\r\n\
-             --abbc761f78ff4d7cb7573b5a23f96ef0\r\n\
-             Content-Disposition: form-data; name=\"files_param\"; filename=\"fn2txt\"\r\n\
-             Content-Type: text/plain; charset=utf-8\r\n\r\n\
-             test\r\n\
-             --abbc761f78ff4d7cb7573b5a23f96ef0\r\n\
-             Content-Disposition: form-data; name=\"files_param\"; filename=\"fn3.txt\"\r\n\
-             Content-Type: text/plain; charset=utf-8\r\n\r\n\
-             test\r\n\
-             --abbc761f78ff4d7cb7573b5a23f96ef0--\r\n",
-        );
-        let mut headers = HeaderMap::new();
-        headers.insert(
-            header::CONTENT_TYPE,
-            header::HeaderValue::from_static(
-                "multipart/mixed; boundary=\"abbc761f78ff4d7cb7573b5a23f96ef0\"",
-            ),
-        );
-        (bytes, headers)
+        MultipartBuilder::new()
+            .boundary(BOUNDARY)
+            .file("files_param", "fn.txt", "text/plain; charset=utf-8", "test")
+            .file("files_param", "fn2txt", "text/plain; charset=utf-8", "test")
+            .file(
+                "files_param",
+                "fn3.txt",
+                "text/plain; charset=utf-8",
+                "test",
+            )
+            .build()
+            .unwrap()
     }
     fn create_simple_request_with_header_empty_file() -> (Bytes, HeaderMap) {
-        let bytes = Bytes::from(
-            "testasdadsad\r\n\
-             --abbc761f78ff4d7cb7573b5a23f96ef0\r\n\
-             Content-Disposition: form-data; name=\"file_param\"; filename=\"fn.txt\"\r\n\
-             Content-Type: text/plain; charset=utf-8\r\n\r\n\
-             \r\n\
-             --abbc761f78ff4d7cb7573b5a23f96ef0\r\n\
-             Content-Disposition: form-data; name=\"u32_param\"\r\n\r\n\
-             56\r\n\
-             --abbc761f78ff4d7cb7573b5a23f96ef0\r\n\
-             Content-Disposition: form-data; name=\"i32_param\"\r\n\r\n\
-             -12\r\n\
-             --abbc761f78ff4d7cb7573b5a23f96ef0\r\n\
-             Content-Disposition: form-data; name=\"first_param\"\r\n\r\n\
-             A simple test\r\n\
-             --abbc761f78ff4d7cb7573b5a23f96ef0--\r\n",
-        );
-        let mut headers = HeaderMap::new();
-        headers.insert(
-            header::CONTENT_TYPE,
-            header::HeaderValue::from_static(
-                "multipart/mixed; boundary=\"abbc761f78ff4d7cb7573b5a23f96ef0\"",
-            ),
-        );
-        (bytes, headers)
+        MultipartBuilder::new()
+            .boundary(BOUNDARY)
+            .file("file_param", "fn.txt", "text/plain; charset=utf-8", "")
+            .text("u32_param", "56")
+            .text("i32_param", "-12")
+            .text("first_param", "A simple test")
+            .build()
+            .unwrap()
     }
     fn create_simple_request_with_header_with_no_file() -> (Bytes, HeaderMap) {
-        let bytes = Bytes::from(
-            "testasdadsad\r\n\
-             --abbc761f78ff4d7cb7573b5a23f96ef0\r\n\
-             Content-Disposition: form-data; name=\"u32_param\"\r\n\r\n\
-             56\r\n\
-             --abbc761f78ff4d7cb7573b5a23f96ef0\r\n\
-             Content-Disposition: form-data; name=\"i32_param\"\r\n\r\n\
-             -12\r\n\
-             --abbc761f78ff4d7cb7573b5a23f96ef0\r\n\
-             Content-Disposition: form-data; name=\"first_param\"\r\n\r\n\
-             A simple test\r\n\
-             --abbc761f78ff4d7cb7573b5a23f96ef0--\r\n",
-        );
-        let mut headers = HeaderMap::new();
-        headers.insert(
-            header::CONTENT_TYPE,
-            header::HeaderValue::from_static(
-                "multipart/mixed; boundary=\"abbc761f78ff4d7cb7573b5a23f96ef0\"",
-            ),
-        );
-        (bytes, headers)
+        MultipartBuilder::new()
+            .boundary(BOUNDARY)
+            .text("u32_param", "56")
+            .text("i32_param", "-12")
+            .text("first_param", "A simple test")
+            .build()
+            .unwrap()
     }
     fn create_simple_request_with_header_with_2_u32() -> (Bytes, HeaderMap) {
-        let bytes = Bytes::from(
-            "testasdadsad\r\n\
-             --abbc761f78ff4d7cb7573b5a23f96ef0\r\n\
-             Content-Disposition: form-data; name=\"param1\"\r\n\r\n\
-             56\r\n\
-             --abbc761f78ff4d7cb7573b5a23f96ef0\r\n\
-             Content-Disposition: form-data; name=\"param2\"\r\n\r\n\
-             24\r\n\
-             --abbc761f78ff4d7cb7573b5a23f96ef0--\r\n",
-        );
-        let mut headers = HeaderMap::new();
-        headers.insert(
-            header::CONTENT_TYPE,
-            header::HeaderValue::from_static(
-                "multipart/mixed; boundary=\"abbc761f78ff4d7cb7573b5a23f96ef0\"",
-            ),
-        );
-        (bytes, headers)
+        MultipartBuilder::new()
+            .boundary(BOUNDARY)
+            .text("param1", "56")
+            .text("param2", "24")
+            .build()
+            .unwrap()
+    }
+    #[cfg(feature = "md5")]
+    fn create_simple_request_with_digest_header(content_md5: &str) -> (Bytes, HeaderMap) {
+        MultipartBuilder::new()
+            .boundary(BOUNDARY)
+            .part(
+                Part::file("file_param", "fn.txt", "text/plain; charset=utf-8", "test")
+                    .header("Content-MD5", content_md5),
+            )
+            .build()
+            .unwrap()
     }
 
     #[allow(dead_code)]
@@ -450,9 +864,9 @@ mod tests {
 
         sender.send(Ok(bytes)).unwrap();
 
-        let actix_multipart = actix_multipart::Multipart::new(&headers, payload);
+        let multipart = MultipartReader::new(&headers, payload);
 
-        match extract_multipart::<Test>(actix_multipart).await {
+        match extract_multipart::<Test>(multipart).await {
             Ok(data) => assert_eq!(data.file_param.len(), 4),
             Err(_) => panic!("Failed to parse multipart into structure"),
         }
@@ -475,9 +889,9 @@ mod tests {
 
         sender.send(Ok(bytes)).unwrap();
 
-        let actix_multipart = actix_multipart::Multipart::new(&headers, payload);
+        let multipart = MultipartReader::new(&headers, payload);
 
-        match extract_multipart::<Test>(actix_multipart).await {
+        match extract_multipart::<Test>(multipart).await {
             Ok(_) => panic!(
                 "Types not matching, but parsing was a success. It should have return an Err(_)"
             ),
@@ -500,9 +914,9 @@ mod tests {
 
         sender.send(Ok(bytes)).unwrap();
 
-        let actix_multipart = actix_multipart::Multipart::new(&headers, payload);
+        let multipart = MultipartReader::new(&headers, payload);
 
-        match extract_multipart::<Test>(actix_multipart).await {
+        match extract_multipart::<Test>(multipart).await {
             Ok(data) => assert_eq!(data.string_param.len(), 3),
             Err(_) => panic!("Failed to parse multipart into structure"),
         }
@@ -524,9 +938,9 @@ mod tests {
 
         sender.send(Ok(bytes)).unwrap();
 
-        let actix_multipart = actix_multipart::Multipart::new(&headers, payload);
+        let multipart = MultipartReader::new(&headers, payload);
 
-        match extract_multipart::<Test>(actix_multipart).await {
+        match extract_multipart::<Test>(multipart).await {
             Ok(data) => assert_eq!(data.file_param.is_none(), true),
             Err(_) => panic!("Failed to parse multipart into structure"),
         }
@@ -548,9 +962,9 @@ mod tests {
 
         sender.send(Ok(bytes)).unwrap();
 
-        let actix_multipart = actix_multipart::Multipart::new(&headers, payload);
+        let multipart = MultipartReader::new(&headers, payload);
 
-        match extract_multipart::<Test>(actix_multipart).await {
+        match extract_multipart::<Test>(multipart).await {
             Ok(data) => assert_eq!(data.file_param.is_some(), true),
             Err(_) => panic!("Failed to parse multipart into structure"),
         }
@@ -572,9 +986,9 @@ mod tests {
 
         sender.send(Ok(bytes)).unwrap();
 
-        let actix_multipart = actix_multipart::Multipart::new(&headers, payload);
+        let multipart = MultipartReader::new(&headers, payload);
 
-        match extract_multipart::<Test>(actix_multipart).await {
+        match extract_multipart::<Test>(multipart).await {
             Ok(data) => assert_eq!(data.file_param.is_none(), true),
             Err(_) => panic!("Failed to parse multipart into structure"),
         }
@@ -592,9 +1006,9 @@ mod tests {
 
         sender.send(Ok(bytes)).unwrap();
 
-        let actix_multipart = actix_multipart::Multipart::new(&headers, payload);
+        let multipart = MultipartReader::new(&headers, payload);
 
-        match extract_multipart::<Test>(actix_multipart).await {
+        match extract_multipart::<Test>(multipart).await {
             Ok(data) => assert_eq!((data.files_param.len() == 3), true),
             Err(_) => panic!("Failed to parse multipart into structure"),
         }
@@ -611,9 +1025,9 @@ mod tests {
 
         sender.send(Ok(bytes)).unwrap();
 
-        let actix_multipart = actix_multipart::Multipart::new(&headers, payload);
+        let multipart = MultipartReader::new(&headers, payload);
 
-        match extract_multipart::<Test>(actix_multipart).await {
+        match extract_multipart::<Test>(multipart).await {
             Ok(data) => assert_eq!((data.files_param.len() == 1), true),
             Err(_) => panic!("Failed to parse multipart into structure"),
         }
@@ -635,9 +1049,9 @@ mod tests {
 
         sender.send(Ok(bytes)).unwrap();
 
-        let actix_multipart = actix_multipart::Multipart::new(&headers, payload);
+        let multipart = MultipartReader::new(&headers, payload);
 
-        match extract_multipart::<Test>(actix_multipart).await {
+        match extract_multipart::<Test>(multipart).await {
             Ok(data) => assert_eq!((data.files_param.len() == 3), true),
             Err(_) => panic!("When uploading multiple files with one field, the field name need to have hooks [] at the end")
         }
@@ -656,9 +1070,9 @@ mod tests {
 
         sender.send(Ok(bytes)).unwrap();
 
-        let actix_multipart = actix_multipart::Multipart::new(&headers, payload);
+        let multipart = MultipartReader::new(&headers, payload);
 
-        match extract_multipart::<Test>(actix_multipart).await {
+        match extract_multipart::<Test>(multipart).await {
             Ok(data) => assert_eq!(
                 if data.param1 == 56 && data.param2 == 24 {
                     true
@@ -670,4 +1084,529 @@ mod tests {
             Err(_) => panic!("Failed to parse multipart into structure"),
         }
     }
+
+    #[cfg(all(feature = "sha2", feature = "md5", feature = "blake3"))]
+    #[actix_rt::test]
+    async fn test_digests_computed_during_upload() {
+        #[derive(Deserialize)]
+        struct Test {
+            file_param: File,
+        }
+
+        let (sender, payload) = create_stream();
+        let (bytes, headers) = create_simple_request_with_digest_header("CY9rzUYh03PK3k6DJie09g==");
+
+        sender.send(Ok(bytes)).unwrap();
+
+        let multipart = MultipartReader::new(&headers, payload);
+        let config = MultipartConfig::default().digest(Algorithm::Sha256);
+
+        match extract_multipart_with_config::<Test>(multipart, &config).await {
+            Ok(data) => {
+                assert_eq!(data.file_param.digest(Algorithm::Md5).unwrap().len(), 16);
+                assert_eq!(
+                    data.file_param.digest(Algorithm::Sha256).unwrap()[..4],
+                    [0x9f, 0x86, 0xd0, 0x81]
+                );
+                assert!(data.file_param.digest(Algorithm::Blake3).is_none());
+            }
+            Err(_) => panic!("Failed to parse multipart into structure"),
+        }
+    }
+
+    #[cfg(feature = "md5")]
+    #[allow(dead_code)]
+    #[actix_rt::test]
+    async fn test_digest_mismatch_rejected() {
+        #[derive(Deserialize)]
+        struct Test {
+            file_param: File,
+        }
+
+        let (sender, payload) = create_stream();
+        let (bytes, headers) = create_simple_request_with_digest_header("1B2M2Y8AsgTpgAmY7PhCfg==");
+
+        sender.send(Ok(bytes)).unwrap();
+
+        let multipart = MultipartReader::new(&headers, payload);
+
+        match extract_multipart::<Test>(multipart).await {
+            Ok(_) => panic!("Digest doesn't match, but parsing was a success"),
+            Err(err) => assert!(matches!(
+                err,
+                MultipartError::DigestMismatch {
+                    algorithm: Algorithm::Md5,
+                    ..
+                }
+            )),
+        }
+    }
+
+    #[cfg(feature = "md5")]
+    #[allow(dead_code)]
+    #[actix_rt::test]
+    async fn test_digest_of_empty_parts_checked() {
+        #[derive(Deserialize)]
+        struct Test {
+            file_param: Option<File>,
+            text_param: Option<String>,
+        }
+
+        for part in [
+            Part::file("file_param", "fn.txt", "text/plain", ""),
+            Part::text("text_param", ""),
+        ] {
+            let (bytes, headers) = MultipartBuilder::new()
+                .part(part.header("Content-MD5", "CY9rzUYh03PK3k6DJie09g=="))
+                .build()
+                .unwrap();
+            let (sender, payload) = create_stream();
+            sender.send(Ok(bytes)).unwrap();
+
+            let err = extract_multipart::<Test>(MultipartReader::new(&headers, payload))
+                .await
+                .err();
+            assert!(matches!(
+                err,
+                Some(MultipartError::DigestMismatch {
+                    algorithm: Algorithm::Md5,
+                    ..
+                })
+            ));
+        }
+    }
+
+    #[actix_rt::test]
+    async fn test_binary_file_from_builder() {
+        #[derive(Deserialize)]
+        struct Test {
+            file_param: File,
+        }
+
+        let data: Vec<u8> = (0..=255).chain(b"\r\n--".iter().copied()).collect();
+        let (req, mut payload) = MultipartBuilder::new()
+            .file(
+                "file_param",
+                "fn.bin",
+                "application/octet-stream",
+                data.clone(),
+            )
+            .test_request()
+            .unwrap()
+            .to_http_parts();
+
+        match Multipart::<Test>::from_request(&req, &mut payload).await {
+            Ok(extracted) => assert_eq!(extracted.file_param.data(), &data),
+            Err(_) => panic!("Failed to parse multipart into structure"),
+        }
+    }
+
+    fn create_simple_request_with_transfer_encoding(file_data: &str) -> (Bytes, HeaderMap) {
+        let file = Part::file("file_param", "fn.txt", "text/plain", file_data.to_owned())
+            .header("Content-Transfer-Encoding", "base64");
+        #[cfg(feature = "md5")]
+        let file = file.header("Content-MD5", "CY9rzUYh03PK3k6DJie09g==");
+
+        MultipartBuilder::new()
+            .boundary(BOUNDARY)
+            .part(
+                Part::text("string_param", "caf=C3=A9 =\r\nau lait")
+                    .header("Content-Transfer-Encoding", "quoted-printable"),
+            )
+            .part(file)
+            .build()
+            .unwrap()
+    }
+
+    #[actix_rt::test]
+    async fn test_transfer_encoding_decoded() {
+        #[derive(Deserialize)]
+        struct Test {
+            string_param: String,
+            file_param: File,
+        }
+
+        let (sender, payload) = create_stream();
+        let (bytes, headers) = create_simple_request_with_transfer_encoding("dGVz\r\ndA==");
+
+        sender.send(Ok(bytes)).unwrap();
+
+        let multipart = MultipartReader::new(&headers, payload);
+        let config = MultipartConfig::default().keep_raw(true);
+
+        match extract_multipart_with_config::<Test>(multipart, &config).await {
+            Ok(data) => {
+                assert_eq!(data.string_param, "café au lait");
+                assert_eq!(data.file_param.data(), b"test");
+                assert_eq!(data.file_param.raw_data().unwrap(), b"dGVz\r\ndA==");
+            }
+            Err(_) => panic!("Failed to parse multipart into structure"),
+        }
+    }
+
+    #[actix_rt::test]
+    async fn test_invalid_transfer_encoding_rejected() {
+        #[allow(dead_code)]
+        #[derive(Deserialize)]
+        struct Test {
+            string_param: String,
+            file_param: File,
+        }
+
+        let (sender, payload) = create_stream();
+        let (bytes, headers) = create_simple_request_with_transfer_encoding("dGVzdA=?");
+
+        sender.send(Ok(bytes)).unwrap();
+
+        let multipart = MultipartReader::new(&headers, payload);
+
+        match extract_multipart::<Test>(multipart).await {
+            Ok(_) => panic!("Data isn't valid base64, but parsing was a success"),
+            Err(err) => assert!(matches!(
+                err,
+                MultipartError::InvalidEncoding { encoding, .. } if encoding == "base64"
+            )),
+        }
+    }
+
+    #[cfg(feature = "gzip")]
+    #[actix_rt::test]
+    async fn test_gzip_part_decompressed() {
+        use std::io::Write;
+
+        #[derive(Deserialize)]
+        struct Test {
+            file_param: File,
+        }
+
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+        encoder.write_all(b"a,b\n1,2\n").unwrap();
+        let (req, mut payload) = MultipartBuilder::new()
+            .part(
+                Part::file(
+                    "file_param",
+                    "fn.csv",
+                    "text/csv",
+                    encoder.finish().unwrap(),
+                )
+                .header("Content-Encoding", "gzip"),
+            )
+            .test_request()
+            .unwrap()
+            .to_http_parts();
+
+        match Multipart::<Test>::from_request(&req, &mut payload).await {
+            Ok(extracted) => assert_eq!(extracted.file_param.data(), b"a,b\n1,2\n"),
+            Err(_) => panic!("Failed to parse multipart into structure"),
+        }
+    }
+
+    #[cfg(all(feature = "gzip", feature = "sha2"))]
+    #[actix_rt::test]
+    async fn test_repr_digest_of_compressed_part() {
+        use sha2::Digest;
+        use std::io::Write;
+
+        #[allow(dead_code)]
+        #[derive(Deserialize)]
+        struct Test {
+            file_param: File,
+        }
+
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+        encoder.write_all(b"a,b\n1,2\n").unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        // Only the digest of the bytes as they were sent matches.
+        for (digested, accepted) in [(&compressed[..], true), (&b"a,b\n1,2\n"[..], false)] {
+            let digest = BASE64.encode(sha2::Sha256::digest(digested));
+            let (req, mut payload) = MultipartBuilder::new()
+                .part(
+                    Part::file("file_param", "fn.csv", "text/csv", compressed.clone())
+                        .header("Content-Encoding", "gzip")
+                        .header("Repr-Digest", format!("sha-256=:{}:", digest)),
+                )
+                .test_request()
+                .unwrap()
+                .to_http_parts();
+
+            let result = Multipart::<Test>::from_request(&req, &mut payload).await;
+            assert_eq!(result.is_ok(), accepted);
+        }
+    }
+
+    #[actix_rt::test]
+    async fn test_unsupported_digest_rejected() {
+        #[allow(dead_code)]
+        #[derive(Deserialize)]
+        struct Test {
+            file_param: File,
+        }
+
+        let (bytes, headers) = MultipartBuilder::new()
+            .part(
+                Part::file("file_param", "fn.txt", "text/plain", "test")
+                    .header("Digest", "UNIXsum=30637"),
+            )
+            .build()
+            .unwrap();
+        let (sender, payload) = create_stream();
+        sender.send(Ok(bytes)).unwrap();
+
+        let multipart = MultipartReader::new(&headers, payload);
+
+        match extract_multipart::<Test>(multipart).await {
+            Ok(_) => panic!("Digest can't be verified, but parsing was a success"),
+            Err(err) => assert!(matches!(
+                err,
+                MultipartError::UnsupportedDigest { algorithm, .. } if algorithm == "unixsum"
+            )),
+        }
+    }
+
+    fn create_simple_request_with_json_part(metadata: &str) -> (Bytes, HeaderMap) {
+        MultipartBuilder::new()
+            .boundary(BOUNDARY)
+            .part(Part::text("metadata", metadata).header("Content-Type", "application/json"))
+            .file("file_param", "fn.txt", "text/plain", "test")
+            .build()
+            .unwrap()
+    }
+
+    #[allow(dead_code)]
+    #[derive(Deserialize)]
+    struct Author {
+        name: String,
+        year: u16,
+    }
+
+    #[allow(dead_code)]
+    #[derive(Deserialize)]
+    struct Metadata {
+        title: String,
+        authors: Vec<Author>,
+    }
+
+    #[allow(dead_code)]
+    #[derive(Deserialize)]
+    struct JsonTest {
+        metadata: Metadata,
+        file_param: File,
+    }
+
+    #[actix_rt::test]
+    async fn test_json_part_into_nested_struct() {
+        let (sender, payload) = create_stream();
+        let (bytes, headers) = create_simple_request_with_json_part(
+            r#"{"title": "2024", "authors": [{"name": "A", "year": 1970}]}"#,
+        );
+
+        sender.send(Ok(bytes)).unwrap();
+
+        let multipart = MultipartReader::new(&headers, payload);
+
+        match extract_multipart::<JsonTest>(multipart).await {
+            Ok(data) => {
+                assert_eq!(data.metadata.title, "2024");
+                assert_eq!(data.metadata.authors[0].year, 1970);
+            }
+            Err(_) => panic!("Failed to parse multipart into structure"),
+        }
+    }
+
+    #[actix_rt::test]
+    async fn test_json_part_error_path() {
+        let (sender, payload) = create_stream();
+        let (bytes, headers) = create_simple_request_with_json_part(
+            r#"{"title": "A title", "authors": [{"name": "A", "year": "1970"}]}"#,
+        );
+
+        sender.send(Ok(bytes)).unwrap();
+
+        let multipart = MultipartReader::new(&headers, payload);
+
+        match extract_multipart::<JsonTest>(multipart).await {
+            Ok(_) => panic!("Year is a string, but parsing was a success"),
+            Err(err) => assert!(matches!(
+                err,
+                MultipartError::InvalidField { path, .. } if path == "metadata.authors[0].year"
+            )),
+        }
+    }
+
+    #[actix_rt::test]
+    async fn test_incomplete_body_rejected() {
+        #[allow(dead_code)]
+        #[derive(Deserialize)]
+        struct Test {
+            string_param: String,
+            file_param: Option<File>,
+        }
+
+        let builder = MultipartBuilder::new()
+            .text("string_param", "A simple test")
+            .file("file_param", "fn.txt", "text/plain", "test");
+        let (body, _) = builder.build().unwrap();
+        let truncated = body.slice(..body.len() - 20);
+
+        for payload in [truncated, Bytes::new()] {
+            let (req, mut payload) = actix_web::test::TestRequest::post()
+                .insert_header(("content-type", builder.content_type()))
+                .set_payload(payload)
+                .to_http_parts();
+            let err = Multipart::<Test>::from_request(&req, &mut payload)
+                .await
+                .err()
+                .unwrap();
+            assert!(matches!(
+                err.as_error::<MultipartError>(),
+                Some(MultipartError::Read(_))
+            ));
+        }
+
+        let (req, mut payload) = actix_web::test::TestRequest::post()
+            .insert_header(("content-type", "multipart/form-data"))
+            .set_payload(builder.build().unwrap().0)
+            .to_http_parts();
+        assert!(Multipart::<Test>::from_request(&req, &mut payload)
+            .await
+            .is_err());
+    }
+
+    #[actix_rt::test]
+    async fn test_json_part_is_not_a_file() {
+        #[allow(dead_code)]
+        #[derive(Deserialize)]
+        struct Test {
+            string_param: String,
+            file_param: File,
+        }
+
+        let (req, mut payload) = MultipartBuilder::new()
+            .text("string_param", "A simple test")
+            .part(
+                Part::text(
+                    "file_param",
+                    r#"{"name": "fn.txt", "file_type": "text/plain", "data": [116]}"#,
+                )
+                .header("Content-Type", "application/json"),
+            )
+            .test_request()
+            .unwrap()
+            .to_http_parts();
+
+        let err = Multipart::<Test>::from_request(&req, &mut payload)
+            .await
+            .err()
+            .unwrap();
+        assert!(matches!(
+            err.as_error::<MultipartError>(),
+            Some(MultipartError::InvalidField { path, .. }) if path == "file_param"
+        ));
+    }
+
+    const NESTED_BODY: &str = "--inner\r\n\
+        Content-Disposition: file; filename=\"a.txt\"\r\n\
+        Content-Type: text/plain\r\n\r\n\
+        A\r\n\
+        --inner\r\n\
+        Content-Disposition: file; filename=\"b.txt\"\r\n\
+        Content-Type: text/plain\r\n\r\n\
+        B\r\n\
+        --inner--\r\n";
+
+    fn create_simple_request_with_nested_multipart(
+        nested_body: &str,
+        nested_boundary: &str,
+    ) -> (Bytes, HeaderMap) {
+        MultipartBuilder::new()
+            .boundary(BOUNDARY)
+            .text("string_param", "A simple test")
+            .part(Part::text("files_param", nested_body).header(
+                "Content-Type",
+                format!("multipart/mixed; boundary={}", nested_boundary),
+            ))
+            .build()
+            .unwrap()
+    }
+
+    #[allow(dead_code)]
+    #[derive(Deserialize)]
+    struct NestedTest {
+        string_param: String,
+        files_param: Vec<File>,
+    }
+
+    #[actix_rt::test]
+    async fn test_nested_multipart_mixed() {
+        let (sender, payload) = create_stream();
+        let (bytes, headers) = create_simple_request_with_nested_multipart(NESTED_BODY, "inner");
+
+        sender.send(Ok(bytes)).unwrap();
+
+        let multipart = MultipartReader::new(&headers, payload);
+
+        match extract_multipart::<NestedTest>(multipart).await {
+            Ok(data) => {
+                assert_eq!(data.files_param.len(), 2);
+                assert_eq!(data.files_param[0].name(), "a.txt");
+                assert_eq!(data.files_param[1].data(), b"B");
+            }
+            Err(_) => panic!("Failed to parse multipart into structure"),
+        }
+    }
+
+    #[actix_rt::test]
+    async fn test_nested_multipart_limit() {
+        let (sender, payload) = create_stream();
+        let (bytes, headers) = create_simple_request_with_nested_multipart(NESTED_BODY, "inner");
+
+        sender.send(Ok(bytes)).unwrap();
+
+        let multipart = MultipartReader::new(&headers, payload);
+        let config = MultipartConfig::default().nested_limit(100);
+
+        match extract_multipart_with_config::<NestedTest>(multipart, &config).await {
+            Ok(_) => panic!("Nested part is larger than the limit, but parsing was a success"),
+            Err(err) => {
+                assert_eq!(err.status_code(), StatusCode::PAYLOAD_TOO_LARGE);
+                assert!(matches!(
+                    err,
+                    MultipartError::LimitExceeded { limit: 100, .. }
+                ))
+            }
+        }
+    }
+
+    #[actix_rt::test]
+    async fn test_nested_multipart_depth_limit() {
+        let body = format!(
+            "--outer\r\n\
+             Content-Type: multipart/mixed; boundary=inner\r\n\r\n\
+             {}\r\n\
+             --outer--\r\n",
+            NESTED_BODY
+        );
+
+        for max_nesting in [1, 2] {
+            let (sender, payload) = create_stream();
+            let (bytes, headers) = create_simple_request_with_nested_multipart(&body, "outer");
+
+            sender.send(Ok(bytes)).unwrap();
+
+            let multipart = MultipartReader::new(&headers, payload);
+            let config = MultipartConfig::default().max_nesting(max_nesting);
+
+            match extract_multipart_with_config::<NestedTest>(multipart, &config).await {
+                Ok(data) => {
+                    assert_eq!(max_nesting, 2);
+                    assert_eq!(data.files_param.len(), 2);
+                }
+                Err(err) => {
+                    assert_eq!(max_nesting, 1);
+                    assert!(matches!(err, MultipartError::NestingTooDeep { .. }));
+                }
+            }
+        }
+    }
 }
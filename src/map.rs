@@ -0,0 +1,500 @@
+use crate::file;
+use crate::params;
+use crate::position::{self, Positions};
+use crate::reader::MultipartReader;
+use crate::{
+    deserialize_value, file_value, params_insert, read_field, FieldValue, MultipartConfig,
+    MultipartError, MultipartValue, PartData,
+};
+use actix_web::http::header::HeaderMap;
+use actix_web::{dev::Payload, Error, FromRequest, HttpRequest};
+use futures_util::future::Future;
+use serde_json::{Map, Value};
+use std::pin::Pin;
+
+/// A received part: its name as sent (`tags[]` keeps its brackets), its headers and its value.
+#[derive(Debug)]
+pub struct MultipartEntry {
+    name: String,
+    headers: HeaderMap,
+    value: MultipartValue,
+    index: usize,
+    offset: usize,
+}
+
+impl MultipartEntry {
+    /// Position of the part in the body, starting at 0. Parts without a name are counted.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Byte offset of the headers of the part in the body.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn headers(&self) -> &HeaderMap {
+        &self.headers
+    }
+
+    pub fn value(&self) -> &MultipartValue {
+        &self.value
+    }
+
+    pub fn into_value(self) -> MultipartValue {
+        self.value
+    }
+}
+
+/// Extracts every part without a target structure, in the order they were sent. Fields sent
+/// several times have one entry per part, and nested `multipart/mixed` parts are one
+/// `MultipartValue::List` entry.
+///
+/// Parts are decoded, checked and limited like with `Multipart<T>`, but text parts are kept
+/// as sent, including empty ones.
+///
+/// ```no_run
+/// # use actix_extract_multipart::MultipartMap;
+/// # use actix_web::HttpResponse;
+/// async fn index(map: MultipartMap) -> HttpResponse {
+///     for (caption, photo) in map.pairs("caption[]", "photo[]") {
+///         let caption = caption.as_text().unwrap_or_default();
+///         let photo = photo.and_then(|photo| photo.as_file());
+///         // ...
+///     }
+///     HttpResponse::Ok().finish()
+/// }
+/// ```
+#[derive(Debug, Default)]
+pub struct MultipartMap {
+    entries: Vec<MultipartEntry>,
+    /// The configuration of the request, for `deserialize`.
+    config: MultipartConfig,
+    /// The path and query parameters merged by the configuration, for `deserialize`.
+    request_params: Map<String, Value>,
+}
+
+impl MultipartMap {
+    /// The first value sent for `name`.
+    pub fn get(&self, name: &str) -> Option<&MultipartValue> {
+        self.entries
+            .iter()
+            .find(|entry| entry.name == name)
+            .map(|entry| &entry.value)
+    }
+
+    /// All the values sent for `name`, in order.
+    pub fn get_all<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a MultipartValue> {
+        self.entries
+            .iter()
+            .filter(move |entry| entry.name == name)
+            .map(|entry| &entry.value)
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.entries.iter().any(|entry| entry.name == name)
+    }
+
+    /// The distinct field names, in the order they first appeared.
+    pub fn names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = Vec::new();
+        for entry in &self.entries {
+            if !names.contains(&entry.name.as_str()) {
+                names.push(&entry.name);
+            }
+        }
+        names
+    }
+
+    /// Pairs each `first` value with the `second` value sent after it, before the next `first`,
+    /// e.g. the `caption[]` and `photo[]` parts of a list of photos.
+    pub fn pairs(
+        &self,
+        first: &str,
+        second: &str,
+    ) -> Vec<(&MultipartValue, Option<&MultipartValue>)> {
+        let mut pairs: Vec<(&MultipartValue, Option<&MultipartValue>)> = Vec::new();
+        for entry in &self.entries {
+            if entry.name == first {
+                pairs.push((&entry.value, None));
+            } else if entry.name == second {
+                if let Some((_, value @ None)) = pairs.last_mut() {
+                    *value = Some(&entry.value);
+                }
+            }
+        }
+        pairs
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &MultipartEntry> {
+        self.entries.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn into_entries(self) -> Vec<MultipartEntry> {
+        self.entries
+    }
+
+    /// Deserializes the fields into `T` as `Multipart<T>` would, with the `MultipartConfig` of
+    /// the request and the path and query parameters it merges, e.g. once the entries were
+    /// inspected or completed.
+    pub fn deserialize<T: serde::de::DeserializeOwned>(self) -> Result<T, MultipartError> {
+        let mut params = Map::new();
+        let mut positions = Positions::default();
+        for entry in self.entries {
+            if entry.value.as_text() == Some("") && !entry.name.ends_with("[]") {
+                continue;
+            }
+            let name_formatted = entry.name.replace("[]", "");
+            match entry.value.into_params_value() {
+                Value::Array(values) if !entry.name.ends_with("[]") => {
+                    if let Value::Array(list) = params
+                        .entry(name_formatted.clone())
+                        .or_insert_with(|| Value::Array(Vec::new()))
+                    {
+                        for index in list.len()..list.len() + values.len() {
+                            let path = format!("{}[{}]", name_formatted, index);
+                            positions.record_at(path, entry.index, entry.offset);
+                        }
+                        list.extend(values);
+                    }
+                }
+                value => {
+                    params_insert(&mut params, &entry.name, &name_formatted, value);
+                    let path = match &params[&name_formatted] {
+                        Value::Array(list) if entry.name.ends_with("[]") => {
+                            format!("{}[{}]", name_formatted, list.len() - 1)
+                        }
+                        _ => name_formatted,
+                    };
+                    positions.record_at(path, entry.index, entry.offset);
+                }
+            }
+        }
+        params::merge_params(
+            &mut params,
+            self.request_params,
+            self.config.get_precedence(),
+        )?;
+        deserialize_value(Value::Object(params), &self.config, &positions)
+            .map_err(|err| positions.locate(err, &self.config))
+    }
+}
+
+impl MultipartValue {
+    /// The value as it's collected by `Multipart<T>`.
+    fn into_params_value(self) -> Value {
+        match self {
+            MultipartValue::Text(text) => Value::String(text),
+            MultipartValue::File(file) => file_value(
+                &file.name,
+                &file.file_type,
+                PartData {
+                    data: file.data,
+                    raw: file.raw,
+                },
+                &file.digests,
+            ),
+            MultipartValue::List(values) => Value::Array(
+                values
+                    .into_iter()
+                    .map(MultipartValue::into_params_value)
+                    .collect(),
+            ),
+            MultipartValue::Structured(value) => value,
+        }
+    }
+}
+
+async fn extract_map(
+    mut payload: MultipartReader,
+    config: MultipartConfig,
+    request_params: Map<String, Value>,
+) -> Result<MultipartMap, MultipartError> {
+    let mut entries = Vec::new();
+
+    while let Some(head) = payload.next_part().await? {
+        let name = match head.name() {
+            Some(name) => name.to_string(),
+            None => continue,
+        };
+
+        let value = match read_field(&mut payload, &head, &name, None, &config)
+            .await
+            .map_err(|err| position::at_part(err, &head, &config))?
+        {
+            FieldValue::Text(text) => MultipartValue::Text(text),
+            FieldValue::File(value) => MultipartValue::File(file::from_value(value)?),
+            FieldValue::Structured(value) => MultipartValue::Structured(value),
+            FieldValue::Nested(files) => MultipartValue::List(
+                files
+                    .into_iter()
+                    .map(|file| Ok(MultipartValue::File(file::from_value(file)?)))
+                    .collect::<Result<_, MultipartError>>()?,
+            ),
+            FieldValue::Skipped => continue,
+        };
+        entries.push(MultipartEntry {
+            name,
+            headers: head.headers().clone(),
+            value,
+            index: head.index(),
+            offset: head.offset(),
+        });
+    }
+
+    Ok(MultipartMap {
+        entries,
+        config,
+        request_params,
+    })
+}
+
+impl FromRequest for MultipartMap {
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let multipart = MultipartReader::new(req.headers(), payload.take());
+        let config = MultipartConfig::from_req(req);
+        let request_params = params::request_params(req, &config);
+
+        Box::pin(async move { Ok(extract_map(multipart, config, request_params?).await?) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{File, Multipart, MultipartBuilder, Part};
+    use serde::Deserialize;
+
+    #[allow(dead_code)]
+    #[derive(Deserialize)]
+    struct Test {
+        title: String,
+        count: u32,
+        photos: Vec<File>,
+    }
+
+    fn request() -> (HttpRequest, Payload) {
+        MultipartBuilder::new()
+            .text("caption[]", "first")
+            .file("photos[]", "a.png", "image/png", "a")
+            .text("title", "A title")
+            .part(Part::text("count", "007").header("X-Origin", "form"))
+            .file("photos[]", "b.png", "image/png", "b")
+            .test_request()
+            .unwrap()
+            .to_http_parts()
+    }
+
+    #[actix_rt::test]
+    async fn test_multipart_map() {
+        let (req, mut payload) = request();
+        let map = MultipartMap::from_request(&req, &mut payload)
+            .await
+            .unwrap();
+
+        assert_eq!(map.len(), 5);
+        assert_eq!(map.names(), ["caption[]", "photos[]", "title", "count"]);
+        assert_eq!(map.get("count").unwrap().as_text(), Some("007"));
+        let count = map.iter().find(|entry| entry.name() == "count").unwrap();
+        assert_eq!(count.headers().get("x-origin").unwrap(), "form");
+        let photos: Vec<&str> = map
+            .get_all("photos[]")
+            .map(|value| value.as_file().unwrap().name().as_str())
+            .collect();
+        assert_eq!(photos, ["a.png", "b.png"]);
+    }
+
+    #[actix_rt::test]
+    async fn test_pairs_in_part_order() {
+        let builder = MultipartBuilder::new()
+            .text("caption[]", "first")
+            .file("photo[]", "a.png", "image/png", "a")
+            .text("caption[]", "")
+            .file("photo[]", "b.png", "image/png", "b")
+            .text("caption[]", "no photo");
+        let prefix = format!("--{}\r\n", builder.get_boundary()).len();
+        let (req, mut payload) = builder.test_request().unwrap().to_http_parts();
+        let map = MultipartMap::from_request(&req, &mut payload)
+            .await
+            .unwrap();
+
+        let first = map.iter().next().unwrap();
+        assert_eq!((first.index(), first.offset()), (0, prefix));
+        assert_eq!(map.iter().last().unwrap().index(), 4);
+
+        let pairs: Vec<(&str, Option<&str>)> = map
+            .pairs("caption[]", "photo[]")
+            .into_iter()
+            .map(|(caption, photo)| {
+                (
+                    caption.as_text().unwrap(),
+                    photo.map(|photo| photo.as_file().unwrap().name().as_str()),
+                )
+            })
+            .collect();
+        assert_eq!(
+            pairs,
+            [
+                ("first", Some("a.png")),
+                ("", Some("b.png")),
+                ("no photo", None)
+            ]
+        );
+    }
+
+    #[actix_rt::test]
+    async fn test_deserialize_with_request_config() {
+        #[allow(dead_code)]
+        #[derive(Deserialize)]
+        struct Known {
+            title: String,
+        }
+
+        let (req, mut payload) = MultipartBuilder::new()
+            .text("title", "A title")
+            .text("count", "7")
+            .test_request()
+            .unwrap()
+            .app_data(MultipartConfig::default().strict(true))
+            .to_http_parts();
+        let map = MultipartMap::from_request(&req, &mut payload)
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            map.deserialize::<Known>(),
+            Err(MultipartError::UnknownFields { fields }) if fields == ["count"]
+        ));
+    }
+
+    #[actix_rt::test]
+    async fn test_deserialize_merges_request_params() {
+        #[allow(dead_code)]
+        #[derive(Deserialize)]
+        struct Upload {
+            project: String,
+            folder_id: u32,
+            title: String,
+        }
+
+        let (req, mut payload) = MultipartBuilder::new()
+            .text("title", "A title")
+            .test_request()
+            .unwrap()
+            .uri("/acme/upload?folder_id=7")
+            .param("project", "acme")
+            .app_data(
+                MultipartConfig::default()
+                    .merge_path(true)
+                    .merge_query(true),
+            )
+            .to_http_parts();
+        let map = MultipartMap::from_request(&req, &mut payload)
+            .await
+            .unwrap();
+
+        assert!(!map.contains("project"));
+        let upload = map.deserialize::<Upload>().unwrap();
+        assert_eq!((upload.project.as_str(), upload.folder_id), ("acme", 7));
+    }
+
+    #[actix_rt::test]
+    async fn test_incomplete_body_rejected() {
+        let builder = MultipartBuilder::new().text("title", "A title");
+        let body = builder.build().unwrap().0;
+        let (req, mut payload) = actix_web::test::TestRequest::post()
+            .insert_header(("content-type", builder.content_type()))
+            .set_payload(body.slice(..body.len() - 10))
+            .to_http_parts();
+
+        let err = MultipartMap::from_request(&req, &mut payload)
+            .await
+            .err()
+            .unwrap();
+        assert!(matches!(
+            err.as_error::<MultipartError>(),
+            Some(MultipartError::Read(_))
+        ));
+    }
+
+    #[actix_rt::test]
+    async fn test_pairs_through_multipart() {
+        #[derive(Deserialize)]
+        struct Photos {
+            caption: Vec<String>,
+            photo: Vec<File>,
+        }
+
+        let builder = MultipartBuilder::new()
+            .text("caption[]", "first")
+            .file("photo[]", "a.png", "image/png", "a")
+            .text("caption[]", "second")
+            .file("photo[]", "b.png", "image/png", "b");
+        let (req, mut payload) = builder.test_request().unwrap().to_http_parts();
+        let photos = Multipart::<Photos>::from_request(&req, &mut payload)
+            .await
+            .unwrap();
+        let pairs: Vec<(&str, &str)> = photos
+            .caption
+            .iter()
+            .zip(&photos.photo)
+            .map(|(caption, photo)| (caption.as_str(), photo.name().as_str()))
+            .collect();
+        assert_eq!(pairs, [("first", "a.png"), ("second", "b.png")]);
+
+        // An empty caption keeps its place in the list, a missing one can only be told apart
+        // with MultipartMap.
+        let builder = MultipartBuilder::new()
+            .text("caption[]", "")
+            .file("photo[]", "a.png", "image/png", "a")
+            .file("photo[]", "b.png", "image/png", "b")
+            .text("caption[]", "third")
+            .file("photo[]", "c.png", "image/png", "c");
+        let (req, mut payload) = builder.test_request().unwrap().to_http_parts();
+        let photos = Multipart::<Photos>::from_request(&req, &mut payload)
+            .await
+            .unwrap();
+        assert_eq!(photos.caption, ["", "third"]);
+        assert_eq!(photos.photo.len(), 3);
+
+        let (req, mut payload) = builder.test_request().unwrap().to_http_parts();
+        let map = MultipartMap::from_request(&req, &mut payload)
+            .await
+            .unwrap();
+        let (caption, photo) = map.pairs("caption[]", "photo[]")[1];
+        assert_eq!(caption.as_text(), Some("third"));
+        assert_eq!(photo.unwrap().as_file().unwrap().name(), "c.png");
+    }
+
+    #[actix_rt::test]
+    async fn test_multipart_map_deserialize() {
+        let (req, mut payload) = request();
+        let map = MultipartMap::from_request(&req, &mut payload)
+            .await
+            .unwrap();
+
+        match map.deserialize::<Test>() {
+            Ok(data) => {
+                assert_eq!(data.title, "A title");
+                assert_eq!(data.count, 7);
+                assert_eq!(data.photos[1].data(), b"b");
+            }
+            Err(_) => panic!("Failed to parse multipart into structure"),
+        }
+    }
+}
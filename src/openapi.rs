@@ -0,0 +1,225 @@
+//! OpenAPI description of multipart request bodies with `utoipa`, enabled by the `utoipa` feature.
+//!
+//! `File` is described as `type: string, format: binary`. `Multipart<T>` describes `T` the way
+//! the extractor reads it: file fields are inlined and array fields are named with the
+//! trailing `[]` that clients have to send.
+
+use crate::{File, Multipart};
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use utoipa::openapi::encoding::EncodingBuilder;
+use utoipa::openapi::request_body::{RequestBody, RequestBodyBuilder};
+use utoipa::openapi::schema::{ArrayItems, KnownFormat, ObjectBuilder, SchemaFormat, Type};
+use utoipa::openapi::{ContentBuilder, Ref, RefOr, Required, Schema};
+use utoipa::{PartialSchema, ToSchema};
+
+impl PartialSchema for File {
+    fn schema() -> RefOr<Schema> {
+        ObjectBuilder::new()
+            .schema_type(Type::String)
+            .format(Some(SchemaFormat::KnownFormat(KnownFormat::Binary)))
+            .into()
+    }
+}
+
+impl ToSchema for File {}
+
+impl<T: PartialSchema> PartialSchema for Multipart<T> {
+    fn schema() -> RefOr<Schema> {
+        multipart_schema(T::schema())
+    }
+}
+
+impl<T: ToSchema> ToSchema for Multipart<T> {
+    fn name() -> Cow<'static, str> {
+        Cow::Owned(format!("Multipart_{}", T::name()))
+    }
+}
+
+/// Builds the `multipart/form-data` request body of a `Multipart<T>` handler, with the
+/// content types accepted by each part.
+///
+/// ```no_run
+/// # use actix_extract_multipart::{File, MultipartRequestBody};
+/// # #[derive(utoipa::ToSchema)]
+/// # struct Example {
+/// #     avatar: File,
+/// # }
+/// let body = MultipartRequestBody::new::<Example>()
+///     .content_types("avatar", ["image/png", "image/jpeg"])
+///     .build();
+/// ```
+pub struct MultipartRequestBody {
+    schema: RefOr<Schema>,
+    content_types: BTreeMap<String, Vec<String>>,
+    description: Option<String>,
+}
+
+impl MultipartRequestBody {
+    pub fn new<T: PartialSchema>() -> Self {
+        MultipartRequestBody {
+            schema: Multipart::<T>::schema(),
+            content_types: BTreeMap::new(),
+            description: None,
+        }
+    }
+
+    /// Declares the content types accepted for a field, rendered as the part `encoding`.
+    pub fn content_types<I, S>(mut self, field: &str, content_types: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.content_types
+            .entry(field.replace("[]", ""))
+            .or_default()
+            .extend(content_types.into_iter().map(Into::into));
+        self
+    }
+
+    pub fn description<S: Into<String>>(mut self, description: S) -> Self {
+        self.description = Some(description.into());
+        self
+    }
+
+    pub fn build(self) -> RequestBody {
+        let property_names: Vec<String> = match &self.schema {
+            RefOr::T(Schema::Object(object)) => object.properties.keys().cloned().collect(),
+            _ => Vec::new(),
+        };
+
+        let mut content = ContentBuilder::new().schema(Some(self.schema));
+        for (field, content_types) in self.content_types {
+            let array_name = format!("{}[]", field);
+            let name = if property_names.contains(&array_name) {
+                array_name
+            } else {
+                field
+            };
+            content = content.encoding(
+                name,
+                EncodingBuilder::new()
+                    .content_type(Some(content_types.join(", ")))
+                    .build(),
+            );
+        }
+
+        RequestBodyBuilder::new()
+            .description(self.description)
+            .required(Some(Required::True))
+            .content("multipart/form-data", content.build())
+            .build()
+    }
+}
+
+impl From<MultipartRequestBody> for RequestBody {
+    fn from(body: MultipartRequestBody) -> Self {
+        body.build()
+    }
+}
+
+fn multipart_schema(schema: RefOr<Schema>) -> RefOr<Schema> {
+    let mut object = match schema {
+        RefOr::T(Schema::Object(object)) => object,
+        schema => return schema,
+    };
+
+    let properties = std::mem::take(&mut object.properties);
+    for (name, property) in properties {
+        let property = inline_files(property);
+        let name = if is_array(&property) {
+            let array_name = format!("{}[]", name);
+            for required in object.required.iter_mut() {
+                if *required == name {
+                    *required = array_name.clone();
+                }
+            }
+            array_name
+        } else {
+            name
+        };
+        object.properties.insert(name, property);
+    }
+
+    RefOr::T(Schema::Object(object))
+}
+
+/// References to the `File` component are replaced by the inline binary schema, which is
+/// how code generators and Swagger UI recognize file inputs.
+fn inline_files(schema: RefOr<Schema>) -> RefOr<Schema> {
+    match schema {
+        RefOr::Ref(reference) if is_file_ref(&reference) => File::schema(),
+        RefOr::T(Schema::Array(mut array)) => {
+            if let ArrayItems::RefOrSchema(items) = array.items {
+                array.items = ArrayItems::RefOrSchema(Box::new(inline_files(*items)));
+            }
+            RefOr::T(Schema::Array(array))
+        }
+        RefOr::T(Schema::OneOf(mut one_of)) => {
+            one_of.items = one_of.items.into_iter().map(inline_files).collect();
+            RefOr::T(Schema::OneOf(one_of))
+        }
+        schema => schema,
+    }
+}
+
+fn is_file_ref(reference: &Ref) -> bool {
+    reference.ref_location == format!("#/components/schemas/{}", <File as ToSchema>::name())
+}
+
+fn is_array(schema: &RefOr<Schema>) -> bool {
+    match schema {
+        RefOr::T(Schema::Array(_)) => true,
+        RefOr::T(Schema::OneOf(one_of)) => one_of.items.iter().any(is_array),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[allow(dead_code)]
+    #[derive(ToSchema)]
+    struct Test {
+        title: String,
+        avatar: File,
+        attachment: Option<File>,
+        files_param: Vec<File>,
+        tags: Option<Vec<String>>,
+    }
+
+    #[test]
+    fn test_multipart_schema() {
+        let schema = serde_json::to_value(Multipart::<Test>::schema()).unwrap();
+        let properties = &schema["properties"];
+        let binary = json!({ "type": "string", "format": "binary" });
+
+        assert_eq!(properties["avatar"], binary);
+        assert_eq!(properties["files_param[]"]["type"], "array");
+        assert_eq!(properties["files_param[]"]["items"], binary);
+        assert!(properties["attachment"]["oneOf"]
+            .as_array()
+            .unwrap()
+            .contains(&binary));
+        assert!(properties.get("tags[]").is_some());
+        assert_eq!(
+            schema["required"],
+            json!(["title", "avatar", "files_param[]"])
+        );
+    }
+
+    #[test]
+    fn test_request_body_encoding() {
+        let body = MultipartRequestBody::new::<Test>()
+            .content_types("avatar", ["image/png", "image/jpeg"])
+            .content_types("files_param[]", ["application/pdf"])
+            .build();
+        let body = serde_json::to_value(body).unwrap();
+        let encoding = &body["content"]["multipart/form-data"]["encoding"];
+
+        assert_eq!(encoding["avatar"]["contentType"], "image/png, image/jpeg");
+        assert_eq!(encoding["files_param[]"]["contentType"], "application/pdf");
+    }
+}
@@ -0,0 +1,167 @@
+use crate::{params_insert, MultipartConfig, MultipartError};
+use actix_web::HttpRequest;
+use serde_json::{Map, Value};
+
+/// Which value is kept when a field is sent both in the body and in the path or query
+/// parameters merged with `MultipartConfig::merge_path` and `MultipartConfig::merge_query`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Precedence {
+    /// The field of the body is kept.
+    Body,
+    /// The path or query parameter is kept.
+    Request,
+    /// The request is rejected with `MultipartError::ParamConflict`.
+    #[default]
+    Reject,
+}
+
+/// Collects the path and query parameters enabled in `config`. Path parameters come first,
+/// a query parameter with the same name is ignored, or rejected with `Precedence::Reject`.
+pub(crate) fn request_params(
+    req: &HttpRequest,
+    config: &MultipartConfig,
+) -> Result<Map<String, Value>, MultipartError> {
+    let mut params = Map::new();
+    if config.merges_path() {
+        for (name, value) in req.match_info().iter() {
+            params_insert(
+                &mut params,
+                name,
+                &name.replace("[]", ""),
+                Value::String(value.to_owned()),
+            );
+        }
+    }
+
+    if config.merges_query() {
+        let query = serde_urlencoded::from_str::<Vec<(String, String)>>(req.query_string())
+            .map_err(|err| MultipartError::Deserialize(serde::de::Error::custom(err)))?;
+        let path_names: Vec<String> = params.keys().cloned().collect();
+        for (name, value) in query {
+            let name_formatted = name.replace("[]", "");
+            if path_names.contains(&name_formatted) {
+                if config.get_precedence() == Precedence::Reject {
+                    return Err(MultipartError::ParamConflict {
+                        field: name_formatted,
+                    });
+                }
+                continue;
+            }
+            if !value.is_empty() {
+                params_insert(&mut params, &name, &name_formatted, Value::String(value));
+            }
+        }
+    }
+    Ok(params)
+}
+
+/// Merges the request parameters into the fields collected from the body.
+pub(crate) fn merge_params(
+    params: &mut Map<String, Value>,
+    request_params: Map<String, Value>,
+    precedence: Precedence,
+) -> Result<(), MultipartError> {
+    for (name, value) in request_params {
+        if params.contains_key(&name) {
+            match precedence {
+                Precedence::Body => continue,
+                Precedence::Request => {}
+                Precedence::Reject => return Err(MultipartError::ParamConflict { field: name }),
+            }
+        }
+        params.insert(name, value);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Multipart, MultipartBuilder};
+    use actix_web::FromRequest;
+    use serde::Deserialize;
+
+    #[allow(dead_code)]
+    #[derive(Deserialize)]
+    struct Upload {
+        project: String,
+        folder_id: u32,
+        title: String,
+    }
+
+    async fn extract(
+        config: MultipartConfig,
+        query: &str,
+        title: &str,
+    ) -> Result<Multipart<Upload>, String> {
+        let (req, mut payload) = MultipartBuilder::new()
+            .text("title", title)
+            .test_request()
+            .unwrap()
+            .uri(&format!("/acme/upload?{}", query))
+            .param("project", "acme")
+            .app_data(config)
+            .to_http_parts();
+        Multipart::<Upload>::from_request(&req, &mut payload)
+            .await
+            .map_err(|err| err.to_string())
+    }
+
+    #[actix_rt::test]
+    async fn test_merge_path_and_query() {
+        let config = MultipartConfig::default()
+            .merge_path(true)
+            .merge_query(true);
+
+        match extract(config.clone(), "folder_id=7", "A title").await {
+            Ok(data) => {
+                assert_eq!(data.project, "acme");
+                assert_eq!(data.folder_id, 7);
+                assert_eq!(data.title, "A title");
+            }
+            Err(_) => panic!("Failed to parse multipart into structure"),
+        }
+
+        assert!(
+            extract(MultipartConfig::default(), "folder_id=7", "A title")
+                .await
+                .is_err()
+        );
+        assert_eq!(
+            extract(config.clone(), "folder_id=7&project=other", "A title")
+                .await
+                .err()
+                .unwrap(),
+            "Field \"project\" is sent more than once in the body and the request parameters"
+        );
+        assert!(extract(config, "folder_id=7&title=Query", "A title")
+            .await
+            .is_err());
+    }
+
+    #[actix_rt::test]
+    async fn test_precedence() {
+        let config = MultipartConfig::default()
+            .merge_path(true)
+            .merge_query(true);
+
+        let data = extract(
+            config.clone().precedence(Precedence::Body),
+            "folder_id=7&title=Query&project=other",
+            "A title",
+        )
+        .await
+        .unwrap();
+        assert_eq!(data.title, "A title");
+        assert_eq!(data.project, "acme");
+
+        let data = extract(
+            config.precedence(Precedence::Request),
+            "folder_id=7&title=Query",
+            "A title",
+        )
+        .await
+        .unwrap();
+        assert_eq!(data.title, "Query");
+    }
+}
@@ -0,0 +1,304 @@
+use crate::reader::PartHead;
+use crate::{MultipartConfig, MultipartError};
+use serde::de::{self, DeserializeSeed, Deserializer, IntoDeserializer, MapAccess, Visitor};
+use serde::ser::SerializeStruct;
+use serde::{Deserialize, Serialize, Serializer};
+use std::fmt;
+use std::marker::PhantomData;
+
+/// Paths of the collected values and the parts they were read from, to point errors found
+/// while deserializing to a part, and to fill `Positioned` values.
+#[derive(Default)]
+pub(crate) struct Positions {
+    paths: Vec<(String, usize, usize)>,
+}
+
+impl Positions {
+    pub(crate) fn record(&mut self, path: String, head: &PartHead) {
+        self.record_at(path, head.index(), head.offset());
+    }
+
+    pub(crate) fn record_at(&mut self, path: String, index: usize, offset: usize) {
+        self.paths.push((path, index, offset));
+    }
+
+    /// The index and offset of the part the value at `path` was read from, or is inside of.
+    pub(crate) fn find(&self, path: &str) -> Option<(usize, usize)> {
+        self.paths
+            .iter()
+            .filter(|(value_path, ..)| {
+                path.strip_prefix(value_path.as_str()).is_some_and(|rest| {
+                    rest.is_empty() || rest.starts_with('.') || rest.starts_with('[')
+                })
+            })
+            .max_by_key(|(value_path, ..)| value_path.len())
+            .map(|(_, index, offset)| (*index, *offset))
+    }
+
+    /// Wraps a deserialization error in `MultipartError::AtPart`, when its path is inside a
+    /// value read from a part and `MultipartConfig::positions` is enabled.
+    pub(crate) fn locate(&self, err: MultipartError, config: &MultipartConfig) -> MultipartError {
+        let path = match &err {
+            MultipartError::InvalidField { path, .. } if config.keeps_positions() => path,
+            _ => return err,
+        };
+        match self.find(path) {
+            Some((index, offset)) => MultipartError::AtPart {
+                index,
+                offset,
+                source: Box::new(err),
+            },
+            None => err,
+        }
+    }
+}
+
+/// Struct name `Positioned` is deserialized with, so the field deserializer recognizes it.
+pub(crate) const POSITIONED_TOKEN: &str = "$actix_extract_multipart::Positioned";
+
+const POSITIONED_FIELDS: &[&str] = &["index", "offset", "value"];
+
+/// A field value with the position of the part it was read from, to keep the order of parts
+/// sent for different fields, e.g. `caption[]` and `photo[]` lists:
+///
+/// ```no_run
+/// # use actix_extract_multipart::{File, Positioned};
+/// # use serde::Deserialize;
+/// #[derive(Deserialize)]
+/// struct Album {
+///     caption: Vec<Positioned<String>>,
+///     photo: Vec<Positioned<File>>,
+/// }
+/// ```
+///
+/// Values that weren't read from a part, like merged query parameters, are rejected. Other
+/// formats read it as a structure with `index`, `offset` and `value` fields.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Positioned<V> {
+    index: usize,
+    offset: usize,
+    value: V,
+}
+
+impl<V> Positioned<V> {
+    /// Position of the part in the body, starting at 0.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Byte offset of the headers of the part in the body.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    pub fn value(&self) -> &V {
+        &self.value
+    }
+
+    pub fn into_value(self) -> V {
+        self.value
+    }
+}
+
+impl<'de, V: Deserialize<'de>> Deserialize<'de> for Positioned<V> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_struct(
+            POSITIONED_TOKEN,
+            POSITIONED_FIELDS,
+            PositionedVisitor(PhantomData),
+        )
+    }
+}
+
+struct PositionedVisitor<V>(PhantomData<V>);
+
+impl<'de, V: Deserialize<'de>> Visitor<'de> for PositionedVisitor<V> {
+    type Value = Positioned<V>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a value read from a part")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let (mut index, mut offset, mut value) = (None, None, None);
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "index" => index = Some(map.next_value()?),
+                "offset" => offset = Some(map.next_value()?),
+                "value" => value = Some(map.next_value()?),
+                _ => {
+                    map.next_value::<de::IgnoredAny>()?;
+                }
+            }
+        }
+        Ok(Positioned {
+            index: index.ok_or_else(|| de::Error::missing_field("index"))?,
+            offset: offset.ok_or_else(|| de::Error::missing_field("offset"))?,
+            value: value.ok_or_else(|| de::Error::missing_field("value"))?,
+        })
+    }
+}
+
+impl<V: Serialize> Serialize for Positioned<V> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("Positioned", 3)?;
+        state.serialize_field("index", &self.index)?;
+        state.serialize_field("offset", &self.offset)?;
+        state.serialize_field("value", &self.value)?;
+        state.end()
+    }
+}
+
+/// The fields of a `Positioned` value, handed over by the field deserializer with the
+/// deserializer of the value itself.
+pub(crate) struct PositionedFields<D> {
+    index: usize,
+    offset: usize,
+    value: Option<D>,
+    field: usize,
+}
+
+impl<D> PositionedFields<D> {
+    pub(crate) fn new((index, offset): (usize, usize), value: D) -> Self {
+        PositionedFields {
+            index,
+            offset,
+            value: Some(value),
+            field: 0,
+        }
+    }
+}
+
+impl<'de, D: Deserializer<'de, Error = serde_json::Error>> MapAccess<'de> for PositionedFields<D> {
+    type Error = serde_json::Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Self::Error> {
+        let key = match POSITIONED_FIELDS.get(self.field) {
+            Some(key) => *key,
+            None => return Ok(None),
+        };
+        self.field += 1;
+        seed.deserialize(key.into_deserializer()).map(Some)
+    }
+
+    fn next_value_seed<S: DeserializeSeed<'de>>(
+        &mut self,
+        seed: S,
+    ) -> Result<S::Value, Self::Error> {
+        match self.field {
+            1 => seed.deserialize(self.index.into_deserializer()),
+            2 => seed.deserialize(self.offset.into_deserializer()),
+            _ => match self.value.take() {
+                Some(value) => seed.deserialize(value),
+                None => Err(de::Error::custom("the value was already read")),
+            },
+        }
+    }
+}
+
+/// Wraps an error found while reading a part, when `MultipartConfig::positions` is enabled.
+pub(crate) fn at_part(
+    err: MultipartError,
+    head: &PartHead,
+    config: &MultipartConfig,
+) -> MultipartError {
+    match config.keeps_positions() {
+        true => MultipartError::AtPart {
+            index: head.index(),
+            offset: head.offset(),
+            source: Box::new(err),
+        },
+        false => err,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{File, Multipart, MultipartBuilder, MultipartConfig, MultipartError, Positioned};
+    use actix_web::FromRequest;
+    use serde::Deserialize;
+
+    #[allow(dead_code)]
+    #[derive(Deserialize)]
+    struct Test {
+        title: String,
+        counts: Vec<u32>,
+    }
+
+    async fn extract(config: MultipartConfig) -> actix_web::Error {
+        let (req, mut payload) = MultipartBuilder::new()
+            .text("title", "A title")
+            .text("counts[]", "1")
+            .text("counts[]", "two")
+            .test_request()
+            .unwrap()
+            .app_data(config)
+            .to_http_parts();
+        Multipart::<Test>::from_request(&req, &mut payload)
+            .await
+            .err()
+            .unwrap()
+    }
+
+    #[actix_rt::test]
+    async fn test_error_positions() {
+        let err = extract(MultipartConfig::default().positions(true)).await;
+        let err = err.as_error::<MultipartError>().unwrap();
+        match err {
+            MultipartError::AtPart {
+                index,
+                offset,
+                source,
+            } => {
+                assert_eq!(*index, 2);
+                assert!(*offset > 0);
+                assert!(
+                    matches!(source.as_ref(), MultipartError::InvalidField { path, .. } if path == "counts[1]")
+                );
+            }
+            _ => panic!("Expected the position of the part"),
+        }
+        assert_eq!(err.field_errors()["counts[1]"][0].params["part"], 2);
+
+        let err = extract(MultipartConfig::default()).await;
+        assert!(matches!(
+            err.as_error::<MultipartError>(),
+            Some(MultipartError::InvalidField { .. })
+        ));
+    }
+
+    #[derive(Deserialize)]
+    struct Album {
+        title: Positioned<String>,
+        caption: Vec<Positioned<String>>,
+        photo: Vec<Positioned<File>>,
+    }
+
+    #[actix_rt::test]
+    async fn test_positioned_values() {
+        let (req, mut payload) = MultipartBuilder::new()
+            .text("title", "Holidays")
+            .text("caption[]", "Beach")
+            .file("photo[]", "beach.jpg", "image/jpeg", "1")
+            .file("photo[]", "sunset.jpg", "image/jpeg", "2")
+            .text("caption[]", "Sunset")
+            .test_request()
+            .unwrap()
+            .to_http_parts();
+        let album = Multipart::<Album>::from_request(&req, &mut payload)
+            .await
+            .unwrap();
+
+        assert_eq!(album.title.index(), 0);
+        assert_eq!(album.title.value(), "Holidays");
+        let captions: Vec<usize> = album.caption.iter().map(Positioned::index).collect();
+        assert_eq!(captions, [1, 4]);
+        let photos: Vec<usize> = album.photo.iter().map(Positioned::index).collect();
+        assert_eq!(photos, [2, 3]);
+        assert_eq!(album.photo[1].value().name(), "sunset.jpg");
+        assert!(album.photo[0].offset() < album.photo[1].offset());
+    }
+}
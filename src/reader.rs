@@ -0,0 +1,497 @@
+use actix_web::error::PayloadError;
+use actix_web::http::header::{self, ContentDisposition, HeaderMap, HeaderName, HeaderValue};
+use actix_web::web::{Bytes, BytesMut};
+use futures::{Stream, StreamExt};
+use memchr::memmem;
+use std::fmt;
+use std::pin::Pin;
+
+/// Header blocks larger than this are rejected instead of being buffered.
+const MAX_HEADERS_SIZE: usize = 16 * 1024;
+
+#[derive(Debug)]
+pub(crate) enum ReadError {
+    /// The request isn't multipart, or the boundary is missing.
+    Boundary,
+    Payload(PayloadError),
+    /// The payload ended before the closing delimiter.
+    Incomplete,
+    /// The headers of a part are too large or malformed.
+    Headers,
+    /// A nested part is larger than the limit.
+    TooLarge(usize),
+}
+
+impl fmt::Display for ReadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReadError::Boundary => write!(f, "The multipart boundary is missing"),
+            ReadError::Payload(err) => write!(f, "{}", err),
+            ReadError::Incomplete => write!(f, "The multipart body is incomplete"),
+            ReadError::Headers => write!(f, "The headers of a part are malformed or too large"),
+            ReadError::TooLarge(limit) => write!(f, "A part is larger than {} bytes", limit),
+        }
+    }
+}
+
+/// Headers of a part, parsed before its body is read.
+pub(crate) struct PartHead {
+    headers: HeaderMap,
+    disposition: Option<ContentDisposition>,
+    index: usize,
+    offset: usize,
+}
+
+impl PartHead {
+    pub(crate) fn new(headers: HeaderMap, index: usize, offset: usize) -> Self {
+        let disposition = headers
+            .get(header::CONTENT_DISPOSITION)
+            .and_then(|value| ContentDisposition::from_raw(value).ok());
+        PartHead {
+            headers,
+            disposition,
+            index,
+            offset,
+        }
+    }
+
+    /// Position of the part in the body, starting at 0.
+    pub(crate) fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Byte offset of the headers of the part in the body.
+    pub(crate) fn offset(&self) -> usize {
+        self.offset
+    }
+
+    pub(crate) fn headers(&self) -> &HeaderMap {
+        &self.headers
+    }
+
+    pub(crate) fn name(&self) -> Option<&str> {
+        self.disposition.as_ref()?.get_name()
+    }
+
+    pub(crate) fn filename(&self) -> Option<&str> {
+        self.disposition.as_ref()?.get_filename()
+    }
+
+    /// The `Content-Type` of the part as it was sent, `application/octet-stream` by default.
+    pub(crate) fn content_type(&self) -> &str {
+        self.headers
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .map(str::trim)
+            .filter(|value| !value.is_empty())
+            .unwrap_or("application/octet-stream")
+    }
+
+    /// The content type without its parameters, in lowercase.
+    pub(crate) fn essence(&self) -> String {
+        essence(self.content_type())
+    }
+
+    /// The boundary of a nested `multipart/*` part.
+    pub(crate) fn boundary(&self) -> Option<String> {
+        if !self.essence().starts_with("multipart/") {
+            return None;
+        }
+        parameter(self.content_type(), "boundary")
+    }
+}
+
+pub(crate) fn essence(content_type: &str) -> String {
+    content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase()
+}
+
+/// Value of a `Content-Type` parameter, unquoted.
+pub(crate) fn parameter(content_type: &str, name: &str) -> Option<String> {
+    let mut rest = content_type.split_once(';')?.1;
+    loop {
+        let (key, value) = rest.split_once('=')?;
+        let value = value.trim_start();
+        let (value, next) = match value.strip_prefix('"') {
+            Some(quoted) => {
+                let mut unquoted = String::new();
+                let mut chars = quoted.char_indices();
+                let end = loop {
+                    match chars.next()? {
+                        (_, '\\') => unquoted.push(chars.next()?.1),
+                        (i, '"') => break i + 1,
+                        (_, c) => unquoted.push(c),
+                    }
+                };
+                let next = quoted[end..].split_once(';').map(|(_, next)| next);
+                (unquoted, next)
+            }
+            None => match value.split_once(';') {
+                Some((value, next)) => (value.trim().to_owned(), Some(next)),
+                None => (value.trim().to_owned(), None),
+            },
+        };
+        if key.trim().eq_ignore_ascii_case(name) {
+            return Some(value);
+        }
+        rest = next?;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Preamble,
+    AfterDelimiter,
+    Headers,
+    Body,
+    Epilogue,
+}
+
+/// Streaming parser of `multipart/*` bodies (RFC 2046), used for request bodies and for the
+/// `multipart/mixed` bodies nested in their parts.
+///
+/// Unlike `actix_multipart`, it accepts nested multipart parts, any multipart subtype, parts
+/// without a `Content-Disposition`, and bodies using bare `\n` line endings. Parts are read
+/// one after the other: `next_part` returns the headers of the next part, then `chunk`
+/// returns its body.
+pub(crate) struct MultipartReader {
+    stream: Pin<Box<dyn Stream<Item = Result<Bytes, PayloadError>>>>,
+    buffer: BytesMut,
+    /// Finds `--boundary`.
+    delimiter: memmem::Finder<'static>,
+    /// Finds `\n--boundary`, a `\r` before it is also removed from the body.
+    body_delimiter: memmem::Finder<'static>,
+    state: State,
+    error: Option<ReadError>,
+    eof: bool,
+    /// Bytes of the body removed from the buffer so far.
+    consumed: usize,
+    parts: usize,
+}
+
+impl MultipartReader {
+    /// Reads the boundary from the `Content-Type` header. Without one, the first call to
+    /// `next_part` fails.
+    pub(crate) fn new<S>(headers: &HeaderMap, stream: S) -> Self
+    where
+        S: Stream<Item = Result<Bytes, PayloadError>> + 'static,
+    {
+        let boundary = headers
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .filter(|content_type| essence(content_type).starts_with("multipart/"))
+            .and_then(|content_type| parameter(content_type, "boundary"));
+
+        let mut reader = MultipartReader::from_boundary(boundary.as_deref().unwrap_or(""), stream);
+        if boundary.is_none() {
+            reader.error = Some(ReadError::Boundary);
+        }
+        reader
+    }
+
+    pub(crate) fn from_boundary<S>(boundary: &str, stream: S) -> Self
+    where
+        S: Stream<Item = Result<Bytes, PayloadError>> + 'static,
+    {
+        let delimiter = format!("--{}", boundary);
+        let body_delimiter = format!("\n{}", delimiter);
+
+        MultipartReader {
+            stream: Box::pin(stream),
+            buffer: BytesMut::new(),
+            delimiter: memmem::Finder::new(delimiter.as_bytes()).into_owned(),
+            body_delimiter: memmem::Finder::new(body_delimiter.as_bytes()).into_owned(),
+            state: State::Preamble,
+            error: None,
+            eof: false,
+            consumed: 0,
+            parts: 0,
+        }
+    }
+
+    /// Parses a complete body held in memory, such as a nested part.
+    pub(crate) fn from_bytes(boundary: &str, body: Bytes) -> Self {
+        MultipartReader::from_boundary(boundary, futures::stream::iter([Ok(body)]))
+    }
+
+    fn consume(&mut self, len: usize) -> BytesMut {
+        self.consumed += len;
+        self.buffer.split_to(len)
+    }
+
+    /// Reads more of the payload into the buffer, and fails at the end of the payload.
+    async fn fill(&mut self) -> Result<(), ReadError> {
+        if self.eof {
+            return Err(ReadError::Incomplete);
+        }
+        match self.stream.next().await {
+            Some(Ok(chunk)) => {
+                self.buffer.extend_from_slice(&chunk);
+                Ok(())
+            }
+            Some(Err(err)) => Err(ReadError::Payload(err)),
+            None => {
+                self.eof = true;
+                Err(ReadError::Incomplete)
+            }
+        }
+    }
+
+    /// Skips the rest of the current part and returns the headers of the next one, or `None`
+    /// after the closing delimiter.
+    pub(crate) async fn next_part(&mut self) -> Result<Option<PartHead>, ReadError> {
+        if let Some(err) = self.error.take() {
+            self.state = State::Epilogue;
+            return Err(err);
+        }
+
+        loop {
+            match self.state {
+                State::Preamble => match self.find_first_delimiter() {
+                    Some(end) => {
+                        let _ = self.consume(end);
+                        self.state = State::AfterDelimiter;
+                    }
+                    None => {
+                        let keep = self.delimiter.needle().len();
+                        if self.buffer.len() > keep {
+                            let _ = self.consume(self.buffer.len() - keep);
+                        }
+                        self.fill().await?;
+                    }
+                },
+                State::AfterDelimiter => {
+                    if self.buffer.starts_with(b"--") {
+                        self.state = State::Epilogue;
+                        continue;
+                    }
+                    let padding = self
+                        .buffer
+                        .iter()
+                        .position(|byte| *byte != b' ' && *byte != b'\t');
+                    match padding.map(|start| &self.buffer[start..]) {
+                        Some(rest) if rest.starts_with(b"\r\n") => {
+                            let _ = self.consume(padding.unwrap_or_default() + 2);
+                            self.state = State::Headers;
+                        }
+                        Some(rest) if rest.starts_with(b"\n") => {
+                            let _ = self.consume(padding.unwrap_or_default() + 1);
+                            self.state = State::Headers;
+                        }
+                        Some(rest) if rest != b"\r" && rest != b"-" => {
+                            return Err(ReadError::Incomplete)
+                        }
+                        _ if self.buffer.len() > MAX_HEADERS_SIZE => {
+                            return Err(ReadError::Headers)
+                        }
+                        _ => self.fill().await?,
+                    }
+                }
+                State::Headers => match headers_end(&self.buffer) {
+                    Some(end) if end <= MAX_HEADERS_SIZE => {
+                        let offset = self.consumed;
+                        let block = self.consume(end);
+                        self.state = State::Body;
+                        let head = PartHead::new(parse_headers(&block)?, self.parts, offset);
+                        self.parts += 1;
+                        return Ok(Some(head));
+                    }
+                    Some(_) => return Err(ReadError::Headers),
+                    None if self.buffer.len() > MAX_HEADERS_SIZE => return Err(ReadError::Headers),
+                    None => self.fill().await?,
+                },
+                State::Body => while self.chunk().await?.is_some() {},
+                State::Epilogue => return Ok(None),
+            }
+        }
+    }
+
+    /// Returns the next chunk of the body of the current part, or `None` once it is complete.
+    pub(crate) async fn chunk(&mut self) -> Result<Option<Bytes>, ReadError> {
+        while self.state == State::Body {
+            if let Some(position) = self.body_delimiter.find(&self.buffer) {
+                let end = match position > 0 && self.buffer[position - 1] == b'\r' {
+                    true => position - 1,
+                    false => position,
+                };
+                let data = self.consume(end).freeze();
+                let _ = self.consume(position - end + self.body_delimiter.needle().len());
+                self.state = State::AfterDelimiter;
+                return Ok((!data.is_empty()).then_some(data));
+            }
+
+            // The end of the buffer may be the start of the delimiter, preceded by `\r`.
+            let keep = self.body_delimiter.needle().len();
+            if self.buffer.len() > keep {
+                let data = self.consume(self.buffer.len() - keep).freeze();
+                return Ok(Some(data));
+            }
+            self.fill().await?;
+        }
+        Ok(None)
+    }
+
+    /// End of the first delimiter line: `--boundary` at the start of the body or of a line.
+    fn find_first_delimiter(&self) -> Option<usize> {
+        self.delimiter
+            .find_iter(&self.buffer)
+            .find(|&position| position == 0 || self.buffer[position - 1] == b'\n')
+            .map(|position| position + self.delimiter.needle().len())
+    }
+}
+
+/// End of the header block at the start of `buffer`, including the empty line.
+fn headers_end(buffer: &[u8]) -> Option<usize> {
+    let mut start = 0;
+    loop {
+        let end = start + memchr::memchr(b'\n', &buffer[start..])?;
+        let line = &buffer[start..end];
+        if line.is_empty() || line == b"\r" {
+            return Some(end + 1);
+        }
+        start = end + 1;
+    }
+}
+
+/// Parses a header block of a part, without the empty line.
+fn parse_headers(block: &[u8]) -> Result<HeaderMap, ReadError> {
+    let mut headers = HeaderMap::new();
+    let mut last: Option<(HeaderName, Vec<u8>)> = None;
+
+    let mut insert = |header: Option<(HeaderName, Vec<u8>)>| -> Result<(), ReadError> {
+        if let Some((name, value)) = header {
+            let value = HeaderValue::from_bytes(trim(&value)).map_err(|_| ReadError::Headers)?;
+            headers.append(name, value);
+        }
+        Ok(())
+    };
+
+    for line in block.split(|byte| *byte == b'\n') {
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        if line.is_empty() {
+            continue;
+        }
+        // Folded header lines continue the previous value.
+        if line[0] == b' ' || line[0] == b'\t' {
+            match last.as_mut() {
+                Some((_, value)) => {
+                    value.push(b' ');
+                    value.extend_from_slice(trim(line));
+                }
+                None => return Err(ReadError::Headers),
+            }
+            continue;
+        }
+        insert(last.take())?;
+        let colon = line
+            .iter()
+            .position(|byte| *byte == b':')
+            .ok_or(ReadError::Headers)?;
+        let name = HeaderName::from_bytes(trim(&line[..colon])).map_err(|_| ReadError::Headers)?;
+        last = Some((name, line[colon + 1..].to_vec()));
+    }
+    insert(last)?;
+
+    Ok(headers)
+}
+
+fn trim(value: &[u8]) -> &[u8] {
+    let start = value
+        .iter()
+        .position(|byte| !byte.is_ascii_whitespace())
+        .unwrap_or(value.len());
+    let end = value
+        .iter()
+        .rposition(|byte| !byte.is_ascii_whitespace())
+        .map_or(start, |end| end + 1);
+    &value[start..end]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn read_all(mut reader: MultipartReader) -> Result<Vec<(PartHead, Vec<u8>)>, ReadError> {
+        let mut parts = Vec::new();
+        while let Some(head) = reader.next_part().await? {
+            let mut data = Vec::new();
+            while let Some(chunk) = reader.chunk().await? {
+                data.extend_from_slice(&chunk);
+            }
+            parts.push((head, data));
+        }
+        Ok(parts)
+    }
+
+    fn split_stream(body: &[u8], size: usize) -> impl Stream<Item = Result<Bytes, PayloadError>> {
+        futures::stream::iter(
+            body.chunks(size)
+                .map(|chunk| Ok(Bytes::copy_from_slice(chunk)))
+                .collect::<Vec<_>>(),
+        )
+    }
+
+    const BODY: &[u8] = b"preamble\r\n--b0\r\n\
+        Content-Disposition: form-data; name=\"a\"\r\n\
+        X-Folded: one\r\n two\r\n\r\n\
+        first\r\n--b\r\n\
+        --b0  \n\
+        Content-Type: multipart/mixed; boundary=b1\n\n\
+        --b1\r\n\r\nA\r\n--b1--\
+        \r\n--b0--\r\nepilogue";
+
+    #[actix_rt::test]
+    async fn test_parts_across_chunk_sizes() {
+        for size in 1..BODY.len() {
+            let reader = MultipartReader::from_boundary("b0", split_stream(BODY, size));
+            let parts = read_all(reader).await.unwrap();
+
+            assert_eq!(parts.len(), 2);
+            assert_eq!(parts[0].0.name(), Some("a"));
+            assert_eq!((parts[0].0.index(), parts[0].0.offset()), (0, 16));
+            assert_eq!(parts[0].0.headers().get("x-folded").unwrap(), "one two");
+            assert_eq!(parts[0].1, b"first\r\n--b");
+            assert_eq!(parts[1].0.name(), None);
+            assert_eq!(parts[1].0.boundary().as_deref(), Some("b1"));
+            assert_eq!((parts[1].0.index(), parts[1].0.offset()), (1, 100));
+            assert_eq!(parts[1].1, b"--b1\r\n\r\nA\r\n--b1--");
+
+            let mut nested = MultipartReader::from_bytes("b1", Bytes::from(parts[1].1.clone()));
+            assert!(nested.next_part().await.unwrap().is_some());
+            assert_eq!(nested.chunk().await.unwrap().unwrap(), "A");
+            assert!(nested.next_part().await.unwrap().is_none());
+        }
+    }
+
+    #[actix_rt::test]
+    async fn test_incomplete_and_missing_boundary() {
+        let reader = MultipartReader::from_boundary("b0", split_stream(&BODY[..60], 7));
+        assert!(matches!(read_all(reader).await, Err(ReadError::Incomplete)));
+
+        let reader = MultipartReader::new(&HeaderMap::new(), split_stream(BODY, 7));
+        assert!(matches!(read_all(reader).await, Err(ReadError::Boundary)));
+    }
+
+    #[actix_rt::test]
+    async fn test_headers_too_large() {
+        let body = format!(
+            "--b0\r\nX-Large: {}\r\n\r\ndata\r\n--b0--",
+            "a".repeat(20_000)
+        );
+        let reader = MultipartReader::from_boundary("b0", split_stream(body.as_bytes(), 1000));
+        assert!(matches!(read_all(reader).await, Err(ReadError::Headers)));
+    }
+
+    #[test]
+    fn test_parameter() {
+        let content_type = "multipart/mixed; charset=utf-8; boundary=\"a \\\"b\\\";c\"; start=<x>";
+
+        assert_eq!(parameter(content_type, "Boundary").unwrap(), "a \"b\";c");
+        assert_eq!(parameter(content_type, "start").unwrap(), "<x>");
+        assert!(parameter(content_type, "type").is_none());
+    }
+}
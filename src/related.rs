@@ -0,0 +1,369 @@
+//! `multipart/related` bodies (RFC 2387): a root document, such as a JSON part, and
+//! attachments identified by their `Content-ID`.
+
+use crate::file;
+use crate::position::Positions;
+use crate::reader::{parameter, MultipartReader};
+use crate::{
+    deserialize_fields, expected_digests, file_value, read_part, File, MultipartConfig,
+    MultipartError, PartDigesters,
+};
+use actix_web::http::header;
+use actix_web::{dev::Payload, Error, FromRequest, HttpRequest};
+use futures_util::future::Future;
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::ops::{Deref, DerefMut};
+use std::pin::Pin;
+
+/// Extracts a `multipart/related` body.
+///
+/// The root part is the one named by the `start` parameter of the request `Content-Type`, or
+/// the first part. It is decoded with the format registered for its content type (see
+/// `MultipartConfig::format`); no XML decoder is provided, an XML root needs one registered
+/// for its content type. Every `"cid:..."` string inside the root that names the `Content-ID`
+/// of an attachment is then replaced by it before the root is deserialized into `T`, so the
+/// reference can be deserialized into a `File` field. Other strings are left as they are, and
+/// rejected by `File` fields.
+///
+/// ```no_run
+/// # use actix_extract_multipart::{File, Related};
+/// # use actix_web::HttpResponse;
+/// # use serde::Deserialize;
+/// #[derive(Deserialize)]
+/// struct Message {
+///     subject: String,
+///     // "cid:photo@example.com" in the root JSON document
+///     photo: File,
+/// }
+///
+/// async fn index(message: Related<Message>) -> HttpResponse {
+///     let others = message.attachments();
+///     // ...
+/// #   HttpResponse::Ok().finish()
+/// }
+/// ```
+pub struct Related<T> {
+    data: T,
+    attachments: HashMap<String, File>,
+    without_id: Vec<File>,
+}
+
+impl<T> Related<T> {
+    /// Every part except the root, keyed by `Content-ID` without the angle brackets.
+    /// Attachments referenced from the root are also included.
+    pub fn attachments(&self) -> &HashMap<String, File> {
+        &self.attachments
+    }
+
+    /// The parts other than the root sent without a `Content-ID`, in order.
+    pub fn without_id(&self) -> &[File] {
+        &self.without_id
+    }
+
+    /// The root, the attachments by `Content-ID`, and the parts without one.
+    pub fn into_parts(self) -> (T, HashMap<String, File>, Vec<File>) {
+        (self.data, self.attachments, self.without_id)
+    }
+}
+
+impl<T> Deref for Related<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.data
+    }
+}
+
+impl<T> DerefMut for Related<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.data
+    }
+}
+
+fn content_id(value: &str) -> String {
+    let value = value.trim();
+    value
+        .strip_prefix('<')
+        .and_then(|value| value.strip_suffix('>'))
+        .unwrap_or(value)
+        .to_owned()
+}
+
+/// `cid:` URLs are percent-encoded (RFC 2392).
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Replaces `cid:` references by the attachment they point to. Strings that don't name an
+/// attachment are kept, a `File` field then reports the text it got.
+fn resolve_references(value: &mut Value, attachments: &HashMap<String, Value>) {
+    match value {
+        Value::String(text) => {
+            if let Some(attachment) = text
+                .get(..4)
+                .filter(|scheme| scheme.eq_ignore_ascii_case("cid:"))
+                .and_then(|_| attachments.get(&percent_decode(&text[4..])))
+            {
+                *value = attachment.clone();
+            }
+        }
+        Value::Array(values) => {
+            for value in values {
+                resolve_references(value, attachments);
+            }
+        }
+        Value::Object(map) => {
+            for value in map.values_mut() {
+                resolve_references(value, attachments);
+            }
+        }
+        _ => {}
+    }
+}
+
+async fn extract_related<T>(
+    mut reader: MultipartReader,
+    start: Option<String>,
+    config: &MultipartConfig,
+) -> Result<Related<T>, MultipartError>
+where
+    T: serde::de::DeserializeOwned,
+{
+    let mut root = None;
+    let mut attachments = HashMap::new();
+    let mut without_id = Vec::new();
+    let mut ids = HashSet::new();
+
+    while let Some(head) = reader.next_part().await? {
+        let id = head
+            .headers()
+            .get("content-id")
+            .and_then(|value| value.to_str().ok())
+            .map(content_id);
+        let is_root = match &start {
+            Some(start) => id.as_ref() == Some(start),
+            None => root.is_none(),
+        };
+        let field_name = id.clone().unwrap_or_default();
+        if let Some(id) = id.as_ref().filter(|id| !ids.insert(id.to_string())) {
+            return Err(MultipartError::DuplicatePart { name: id.clone() });
+        }
+
+        let expected = expected_digests(&head, &field_name)?;
+        let mut digesters = PartDigesters::new(Some(config), &expected);
+        let keep_raw = config.keeps_raw() && !is_root;
+        let part = read_part(
+            &mut reader,
+            &head,
+            &field_name,
+            &mut digesters,
+            config,
+            keep_raw,
+        )
+        .await?;
+        let digests = digesters.verify(&field_name, &expected)?;
+
+        if is_root {
+            root = Some((field_name, head.essence(), part.data));
+            continue;
+        }
+        let file = file_value(
+            head.filename().unwrap_or_default(),
+            head.content_type(),
+            part,
+            &digests,
+        );
+        match id {
+            Some(id) => {
+                attachments.insert(id, file);
+            }
+            None => without_id.push(file::from_value(file)?),
+        }
+    }
+
+    let (root_name, essence, data) = root.ok_or_else(|| MultipartError::MissingPart {
+        name: start.unwrap_or_else(|| "root".to_owned()),
+    })?;
+    let mut value = config
+        .formats()
+        .decode(&essence, &data)
+        .ok_or_else(|| MultipartError::InvalidEncoding {
+            field: root_name.clone(),
+            encoding: essence.clone(),
+        })?
+        .map_err(|err| MultipartError::InvalidField {
+            path: root_name,
+            source: serde::de::Error::custom(err),
+        })?;
+    resolve_references(&mut value, &attachments);
+
+    Ok(Related {
+        data: deserialize_fields(value, config, &Positions::default(), false)?,
+        attachments: attachments
+            .into_iter()
+            .map(|(id, file)| Ok((id, file::from_value(file)?)))
+            .collect::<Result<_, MultipartError>>()?,
+        without_id,
+    })
+}
+
+impl<T: serde::de::DeserializeOwned + 'static> FromRequest for Related<T> {
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let reader = MultipartReader::new(req.headers(), payload.take());
+        let start = req
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|content_type| parameter(content_type, "start"))
+            .map(|start| content_id(&start));
+        let config = MultipartConfig::from_req(req);
+
+        Box::pin(async move {
+            match extract_related::<T>(reader, start, &config).await {
+                Ok(related) => Ok(related),
+                Err(err) => Err(err.into()),
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MultipartBuilder, Part};
+    use actix_web::test::TestRequest;
+    use serde::Deserialize;
+
+    #[allow(dead_code)]
+    #[derive(Deserialize)]
+    struct Message {
+        subject: String,
+        photos: Vec<File>,
+    }
+
+    fn create_related_request(root: &str) -> TestRequest {
+        let builder = MultipartBuilder::new()
+            .part(
+                Part::file("photo", "a.png", "image/png", "dGVzdA==")
+                    .header("Content-ID", "<a%b@example.com>")
+                    .header("Content-Transfer-Encoding", "base64"),
+            )
+            .part(
+                Part::text("root", root)
+                    .header("Content-Type", "application/json")
+                    .header("Content-ID", "<root@example.com>"),
+            )
+            .part(
+                Part::file("other", "b.txt", "text/plain", "B")
+                    .header("Content-ID", "<b@example.com>"),
+            );
+        let content_type = format!(
+            "multipart/related; type=\"application/json\"; start=\"<root@example.com>\"; boundary=\"{}\"",
+            builder.get_boundary()
+        );
+
+        builder
+            .test_request()
+            .unwrap()
+            .insert_header((header::CONTENT_TYPE, content_type))
+    }
+
+    #[actix_rt::test]
+    async fn test_related_with_references() {
+        let (req, mut payload) =
+            create_related_request(r#"{"subject": "Photos", "photos": ["cid:a%25b@example.com"]}"#)
+                .to_http_parts();
+
+        match Related::<Message>::from_request(&req, &mut payload).await {
+            Ok(related) => {
+                assert_eq!(related.subject, "Photos");
+                assert_eq!(related.photos[0].data(), b"test");
+                assert_eq!(related.photos[0].name(), "a.png");
+                assert_eq!(related.attachments().len(), 2);
+                assert_eq!(related.attachments()["b@example.com"].data(), b"B");
+            }
+            Err(_) => panic!("Failed to parse multipart into structure"),
+        }
+    }
+
+    #[actix_rt::test]
+    async fn test_related_unknown_reference() {
+        let (req, mut payload) = create_related_request(
+            r#"{"subject": "cid:c@example.com", "photos": ["cid:c@example.com"]}"#,
+        )
+        .to_http_parts();
+
+        match Related::<Message>::from_request(&req, &mut payload).await {
+            Ok(_) => panic!("Reference doesn't exist, but parsing was a success"),
+            Err(err) => assert!(matches!(
+                err.as_error::<MultipartError>(),
+                Some(MultipartError::InvalidField { path, .. }) if path == "photos[0]"
+            )),
+        }
+
+        #[derive(Deserialize)]
+        struct Subject {
+            subject: String,
+        }
+        let (req, mut payload) =
+            create_related_request(r#"{"subject": "cid:c@example.com"}"#).to_http_parts();
+        let related = Related::<Subject>::from_request(&req, &mut payload)
+            .await
+            .unwrap();
+        assert_eq!(related.subject, "cid:c@example.com");
+    }
+
+    #[actix_rt::test]
+    async fn test_related_content_ids() {
+        let builder = MultipartBuilder::new()
+            .part(Part::text("root", "{}").header("Content-Type", "application/json"))
+            .file("first", "a.txt", "text/plain", "A")
+            .file("second", "b.txt", "text/plain", "B");
+        let (req, mut payload) = builder.test_request().unwrap().to_http_parts();
+        let related = Related::<HashMap<String, String>>::from_request(&req, &mut payload)
+            .await
+            .unwrap();
+        assert!(related.attachments().is_empty());
+        assert_eq!(related.without_id().len(), 2);
+        assert_eq!(related.without_id()[1].data(), b"B");
+        let (_, attachments, without_id) = related.into_parts();
+        assert!(attachments.is_empty());
+        assert_eq!(without_id[0].data(), b"A");
+
+        let builder = MultipartBuilder::new()
+            .part(Part::text("root", "{}").header("Content-Type", "application/json"))
+            .part(Part::file("a", "a.txt", "text/plain", "A").header("Content-ID", "<a@x>"))
+            .part(Part::file("b", "b.txt", "text/plain", "B").header("Content-ID", "<a@x>"));
+        let (req, mut payload) = builder.test_request().unwrap().to_http_parts();
+        match Related::<HashMap<String, String>>::from_request(&req, &mut payload).await {
+            Ok(_) => panic!("Content-ID is used twice, but parsing was a success"),
+            Err(err) => assert!(matches!(
+                err.as_error::<MultipartError>(),
+                Some(MultipartError::DuplicatePart { name }) if name == "a@x"
+            )),
+        }
+    }
+}
@@ -0,0 +1,205 @@
+use actix_web::http::header::HeaderMap;
+use base64::alphabet;
+use base64::engine::general_purpose::{GeneralPurpose, GeneralPurposeConfig};
+use base64::engine::DecodePaddingMode;
+use base64::Engine;
+
+/// Line breaks may split the encoded text anywhere, and some clients drop the padding.
+const BASE64: GeneralPurpose = GeneralPurpose::new(
+    &alphabet::STANDARD,
+    GeneralPurposeConfig::new().with_decode_padding_mode(DecodePaddingMode::Indifferent),
+);
+
+/// Streaming decoder for the `Content-Transfer-Encoding` of a part.
+///
+/// Chunks can end in the middle of an encoded sequence; the incomplete tail is kept
+/// until the next chunk or `finish`.
+pub(crate) enum TransferDecoder {
+    Identity,
+    Base64 { pending: Vec<u8> },
+    QuotedPrintable { pending: Vec<u8> },
+}
+
+impl TransferDecoder {
+    /// Returns the unsupported encoding name as the error.
+    pub(crate) fn from_headers(headers: &HeaderMap) -> Result<Self, String> {
+        let encoding = match headers
+            .get("content-transfer-encoding")
+            .and_then(|value| value.to_str().ok())
+        {
+            Some(encoding) => encoding.trim().to_ascii_lowercase(),
+            None => return Ok(TransferDecoder::Identity),
+        };
+
+        match encoding.as_str() {
+            "7bit" | "8bit" | "binary" => Ok(TransferDecoder::Identity),
+            "base64" => Ok(TransferDecoder::Base64 {
+                pending: Vec::new(),
+            }),
+            "quoted-printable" => Ok(TransferDecoder::QuotedPrintable {
+                pending: Vec::new(),
+            }),
+            _ => Err(encoding),
+        }
+    }
+
+    pub(crate) fn is_identity(&self) -> bool {
+        matches!(self, TransferDecoder::Identity)
+    }
+
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            TransferDecoder::Identity => "binary",
+            TransferDecoder::Base64 { .. } => "base64",
+            TransferDecoder::QuotedPrintable { .. } => "quoted-printable",
+        }
+    }
+
+    /// Appends the decoded bytes of `chunk` to `out`.
+    pub(crate) fn decode(&mut self, chunk: &[u8], out: &mut Vec<u8>) -> Result<(), ()> {
+        match self {
+            TransferDecoder::Identity => {
+                out.extend_from_slice(chunk);
+                Ok(())
+            }
+            TransferDecoder::Base64 { pending } => {
+                pending.extend(chunk.iter().filter(|byte| !byte.is_ascii_whitespace()));
+                let complete = pending.len() - pending.len() % 4;
+                BASE64
+                    .decode_vec(&pending[..complete], out)
+                    .map_err(|_| ())?;
+                pending.drain(..complete);
+                Ok(())
+            }
+            TransferDecoder::QuotedPrintable { pending } => {
+                pending.extend_from_slice(chunk);
+                let consumed = decode_quoted_printable(pending, false, out);
+                pending.drain(..consumed);
+                Ok(())
+            }
+        }
+    }
+
+    /// Decodes what is left once the part is complete.
+    pub(crate) fn finish(self, out: &mut Vec<u8>) -> Result<(), ()> {
+        match self {
+            TransferDecoder::Identity => Ok(()),
+            TransferDecoder::Base64 { pending } => BASE64.decode_vec(&pending, out).map_err(|_| ()),
+            TransferDecoder::QuotedPrintable { pending } => {
+                decode_quoted_printable(&pending, true, out);
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Decodes `input` into `out` and returns the number of bytes consumed. Unless `last` is set,
+/// decoding stops before a sequence that may continue in the next chunk: an `=` escape or
+/// whitespace that would have to be removed if it ends the line.
+///
+/// Malformed escapes are kept as-is, as recommended by RFC 2045.
+fn decode_quoted_printable(input: &[u8], last: bool, out: &mut Vec<u8>) -> usize {
+    let mut i = 0;
+    while i < input.len() {
+        match input[i] {
+            b'=' => {
+                let rest = &input[i + 1..];
+                if rest.starts_with(b"\r\n") {
+                    i += 3;
+                } else if rest.starts_with(b"\n") {
+                    i += 2;
+                } else if rest.len() >= 2 {
+                    match std::str::from_utf8(&rest[..2])
+                        .ok()
+                        .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                    {
+                        Some(byte) => {
+                            out.push(byte);
+                            i += 3;
+                        }
+                        None => {
+                            out.push(b'=');
+                            i += 1;
+                        }
+                    }
+                } else if last {
+                    out.extend_from_slice(&input[i..]);
+                    i = input.len();
+                } else {
+                    return i;
+                }
+            }
+            b' ' | b'\t' => {
+                let end = input[i..]
+                    .iter()
+                    .position(|byte| *byte != b' ' && *byte != b'\t')
+                    .map(|position| i + position);
+                match end {
+                    Some(end) => {
+                        let rest = &input[end..];
+                        let line_end = rest.starts_with(b"\r\n") || rest.starts_with(b"\n");
+                        if rest == b"\r" && !last {
+                            return i;
+                        }
+                        if !line_end {
+                            out.extend_from_slice(&input[i..end]);
+                        }
+                        i = end;
+                    }
+                    None if last => i = input.len(),
+                    None => return i,
+                }
+            }
+            byte => {
+                out.push(byte);
+                i += 1;
+            }
+        }
+    }
+    i
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode_chunks(mut decoder: TransferDecoder, chunks: &[&[u8]]) -> Result<Vec<u8>, ()> {
+        let mut out = Vec::new();
+        for chunk in chunks {
+            decoder.decode(chunk, &mut out)?;
+        }
+        decoder.finish(&mut out)?;
+        Ok(out)
+    }
+
+    #[test]
+    fn test_base64_split_across_chunks() {
+        let decoder = TransferDecoder::Base64 {
+            pending: Vec::new(),
+        };
+        let decoded = decode_chunks(decoder, &[b"dGVz", b"dCBk\r\nYX", b"Rh"]).unwrap();
+
+        assert_eq!(decoded, b"test data");
+        assert!(decode_chunks(
+            TransferDecoder::Base64 {
+                pending: Vec::new()
+            },
+            &[b"dG#z"]
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_quoted_printable_split_across_chunks() {
+        let decoder = TransferDecoder::QuotedPrintable {
+            pending: Vec::new(),
+        };
+        let decoded = decode_chunks(
+            decoder,
+            &[b"caf=", b"C3=A9 au lait  ", b"\r\nsoft=\r", b"\nbreak =ZZ"],
+        )
+        .unwrap();
+
+        assert_eq!(decoded, "café au lait\r\nsoftbreak =ZZ".as_bytes());
+    }
+}
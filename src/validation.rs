@@ -0,0 +1,246 @@
+//! Integration with the `validator` crate, enabled by the `validator` feature.
+//!
+//! `ValidatedMultipart<T>` behaves like `Multipart<T>` and then calls `Validate::validate`,
+//! rejecting the request with the field-level errors. The `file_*` functions can be combined
+//! in a `#[validate(custom(function = "..."))]` validator for `File` fields:
+//!
+//! ```no_run
+//! # use actix_extract_multipart::{validation, File};
+//! # use validator::ValidationError;
+//! fn validate_avatar(file: &File) -> Result<(), ValidationError> {
+//!     validation::file_size(file, 1..=2_000_000)?;
+//!     validation::file_mime(file, "image/*")?;
+//!     validation::file_extension(file, &["png", "jpg", "jpeg"])
+//! }
+//! ```
+
+use crate::error::FieldError;
+use crate::{extract_from_request, File, MultipartError};
+use actix_web::{dev::Payload, Error, FromRequest, HttpRequest};
+use futures_util::future::Future;
+use serde_json::{Map, Value};
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::ops::{Bound, Deref, DerefMut, RangeBounds};
+use std::pin::Pin;
+use validator::{Validate, ValidationError, ValidationErrors, ValidationErrorsKind};
+
+/// Extracts `T` like `Multipart<T>`, then calls `Validate::validate` on it. Validation errors
+/// reject the request with `MultipartError::Validation`, which lists them by field.
+pub struct ValidatedMultipart<T> {
+    data: T,
+}
+
+impl<T> ValidatedMultipart<T> {
+    fn new(data: T) -> Self {
+        ValidatedMultipart::<T> { data }
+    }
+}
+
+impl<T> Deref for ValidatedMultipart<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.data
+    }
+}
+
+impl<T> DerefMut for ValidatedMultipart<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.data
+    }
+}
+
+impl<T: serde::de::DeserializeOwned + Validate + 'static> FromRequest for ValidatedMultipart<T> {
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let extraction = extract_from_request::<T>(req, payload);
+
+        Box::pin(async move {
+            let data = extraction.await?;
+            match data.validate() {
+                Ok(()) => Ok(ValidatedMultipart::<T>::new(data)),
+                Err(errors) => Err(MultipartError::Validation(errors).into()),
+            }
+        })
+    }
+}
+
+/// Checks that the file size, in bytes, is within `range`. The error reports the smallest and
+/// largest sizes allowed as `min` and `max`, whether the bounds of `range` are included or not.
+pub fn file_size<R: RangeBounds<usize>>(file: &File, range: R) -> Result<(), ValidationError> {
+    if range.contains(&file.len()) {
+        return Ok(());
+    }
+
+    let mut err = ValidationError::new("file_size");
+    let min = match range.start_bound() {
+        Bound::Included(min) => Some(*min),
+        Bound::Excluded(min) => min.checked_add(1),
+        Bound::Unbounded => None,
+    };
+    let max = match range.end_bound() {
+        Bound::Included(max) => Some(*max),
+        Bound::Excluded(max) => max.checked_sub(1),
+        Bound::Unbounded => None,
+    };
+    if let Some(min) = min {
+        err.add_param(Cow::from("min"), &min);
+    }
+    if let Some(max) = max {
+        err.add_param(Cow::from("max"), &max);
+    }
+    err.add_param(Cow::from("size"), &file.len());
+    Err(err)
+}
+
+/// Checks the file content type against a pattern such as `image/png`, `image/*` or `*/*`.
+/// Parameters like `charset` are ignored.
+pub fn file_mime(file: &File, pattern: &str) -> Result<(), ValidationError> {
+    let essence = file
+        .file_type()
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+    let (file_type, file_subtype) = essence.split_once('/').unwrap_or((&essence, ""));
+    let (pattern_type, pattern_subtype) = pattern.split_once('/').unwrap_or((pattern, "*"));
+
+    let matches =
+        |pattern: &str, value: &str| pattern == "*" || pattern.eq_ignore_ascii_case(value);
+    if matches(pattern_type, file_type) && matches(pattern_subtype, file_subtype) {
+        return Ok(());
+    }
+
+    let mut err = ValidationError::new("file_mime");
+    err.add_param(Cow::from("pattern"), &pattern);
+    err.add_param(Cow::from("file_type"), &essence);
+    Err(err)
+}
+
+/// Checks that the file name ends with one of `extensions` (without the leading dot),
+/// ignoring case.
+pub fn file_extension(file: &File, extensions: &[&str]) -> Result<(), ValidationError> {
+    let extension = file
+        .name()
+        .rsplit_once('.')
+        .map(|(_, extension)| extension)
+        .unwrap_or_default();
+    if extensions.iter().any(|allowed| {
+        allowed
+            .trim_start_matches('.')
+            .eq_ignore_ascii_case(extension)
+    }) {
+        return Ok(());
+    }
+
+    let mut err = ValidationError::new("file_extension");
+    err.add_param(Cow::from("extensions"), &extensions);
+    err.add_param(Cow::from("extension"), &extension);
+    Err(err)
+}
+
+pub(crate) fn collect_field_errors(
+    errors: &ValidationErrors,
+    prefix: &str,
+    fields: &mut BTreeMap<String, Vec<FieldError>>,
+) {
+    for (name, kind) in errors.errors() {
+        let path = if prefix.is_empty() {
+            name.to_string()
+        } else {
+            format!("{}.{}", prefix, name)
+        };
+        match kind {
+            ValidationErrorsKind::Field(errors) => {
+                fields
+                    .entry(path)
+                    .or_default()
+                    .extend(errors.iter().map(|err| {
+                        FieldError {
+                            code: err.code.to_string(),
+                            message: err.message.as_ref().map(|message| message.to_string()),
+                            params: err
+                                .params
+                                .iter()
+                                .filter(|(key, _)| *key != "value")
+                                .map(|(key, value)| (key.to_string(), value.clone()))
+                                .collect::<Map<String, Value>>(),
+                        }
+                    }))
+            }
+            ValidationErrorsKind::Struct(errors) => collect_field_errors(errors, &path, fields),
+            ValidationErrorsKind::List(list) => {
+                for (index, errors) in list {
+                    collect_field_errors(errors, &format!("{}[{}]", path, index), fields);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MultipartBuilder;
+    use actix_web::ResponseError;
+    use serde::Deserialize;
+
+    #[test]
+    fn test_file_validators() {
+        let file = File::for_test("photo.JPG", "image/jpeg", vec![0; 10]);
+
+        assert!(file_size(&file, 1..=10).is_ok());
+        let err = file_size(&file, ..10).unwrap_err();
+        assert_eq!(err.code, "file_size");
+        assert_eq!(err.params["max"], 9);
+        assert_eq!(file_size(&file, 11..).unwrap_err().params["min"], 11);
+        assert!(file_mime(&file, "image/*").is_ok());
+        assert!(file_mime(&file, "*/*").is_ok());
+        assert_eq!(file_mime(&file, "text/*").unwrap_err().code, "file_mime");
+        assert!(file_extension(&file, &["png", "jpg"]).is_ok());
+        assert_eq!(
+            file_extension(&file, &["png"]).unwrap_err().code,
+            "file_extension"
+        );
+    }
+
+    fn validate_photo(file: &File) -> Result<(), ValidationError> {
+        file_size(file, 1..=1024)?;
+        file_mime(file, "image/*")
+    }
+
+    #[allow(dead_code)]
+    #[derive(Deserialize, Validate)]
+    struct Test {
+        #[validate(length(min = 3))]
+        title: String,
+        #[validate(custom(function = "validate_photo"))]
+        photo: File,
+    }
+
+    #[actix_rt::test]
+    async fn test_validation_errors_response() {
+        let (req, mut payload) = MultipartBuilder::new()
+            .text("title", "ab")
+            .file("photo", "fn.txt", "text/plain", "test")
+            .test_request()
+            .unwrap()
+            .to_http_parts();
+
+        let err = ValidatedMultipart::<Test>::from_request(&req, &mut payload)
+            .await
+            .err()
+            .unwrap();
+        let err = err.as_error::<MultipartError>().unwrap();
+        let fields = err.field_errors();
+
+        assert_eq!(err.status_code(), actix_web::http::StatusCode::BAD_REQUEST);
+        assert_eq!(fields["title"][0].code, "length");
+        assert_eq!(fields["photo"][0].code, "file_mime");
+        assert_eq!(fields["photo"][0].params["pattern"], "image/*");
+    }
+}
@@ -0,0 +1,223 @@
+use crate::File;
+use serde::de::value::MapAccessDeserializer;
+use serde::de::{self, Deserializer, MapAccess, SeqAccess, Visitor};
+use serde::forward_to_deserialize_any;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fmt;
+
+/// Any received field, e.g. to collect the parts the target structure doesn't declare:
+///
+/// ```no_run
+/// # use actix_extract_multipart::MultipartValue;
+/// # use serde::Deserialize;
+/// # use std::collections::HashMap;
+/// #[derive(Deserialize)]
+/// struct Upload {
+///     title: String,
+///     #[serde(flatten)]
+///     extra: HashMap<String, MultipartValue>,
+/// }
+/// ```
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+#[non_exhaustive]
+pub enum MultipartValue {
+    /// A text part. Numbers and booleans are kept as they were sent.
+    Text(String),
+    File(File),
+    /// The parts of a `name[]` field, or of a nested `multipart/mixed` part.
+    List(Vec<MultipartValue>),
+    /// A part decoded by a registered format, e.g. `application/json`.
+    Structured(Value),
+}
+
+impl MultipartValue {
+    pub fn as_text(&self) -> Option<&str> {
+        match self {
+            MultipartValue::Text(text) => Some(text),
+            _ => None,
+        }
+    }
+
+    pub fn as_file(&self) -> Option<&File> {
+        match self {
+            MultipartValue::File(file) => Some(file),
+            _ => None,
+        }
+    }
+
+    pub fn as_list(&self) -> Option<&[MultipartValue]> {
+        match self {
+            MultipartValue::List(values) => Some(values),
+            _ => None,
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for MultipartValue {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(ValueVisitor)
+    }
+}
+
+struct ValueVisitor;
+
+impl<'de> Visitor<'de> for ValueVisitor {
+    type Value = MultipartValue;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a multipart value")
+    }
+
+    fn visit_bool<E: de::Error>(self, value: bool) -> Result<MultipartValue, E> {
+        Ok(MultipartValue::Text(value.to_string()))
+    }
+
+    fn visit_i64<E: de::Error>(self, value: i64) -> Result<MultipartValue, E> {
+        Ok(MultipartValue::Text(value.to_string()))
+    }
+
+    fn visit_u64<E: de::Error>(self, value: u64) -> Result<MultipartValue, E> {
+        Ok(MultipartValue::Text(value.to_string()))
+    }
+
+    fn visit_f64<E: de::Error>(self, value: f64) -> Result<MultipartValue, E> {
+        Ok(MultipartValue::Text(Value::from(value).to_string()))
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<MultipartValue, E> {
+        Ok(MultipartValue::Text(value.to_owned()))
+    }
+
+    fn visit_string<E: de::Error>(self, value: String) -> Result<MultipartValue, E> {
+        Ok(MultipartValue::Text(value))
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<MultipartValue, E> {
+        Ok(MultipartValue::Structured(Value::Null))
+    }
+
+    fn visit_none<E: de::Error>(self) -> Result<MultipartValue, E> {
+        Ok(MultipartValue::Structured(Value::Null))
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<MultipartValue, D::Error> {
+        deserializer.deserialize_any(self)
+    }
+
+    /// A received file, see `File::deserialize`.
+    fn visit_newtype_struct<D: Deserializer<'de>>(
+        self,
+        deserializer: D,
+    ) -> Result<MultipartValue, D::Error> {
+        File::deserialize(NewtypeDeserializer(deserializer)).map(MultipartValue::File)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<MultipartValue, A::Error> {
+        let mut values = Vec::with_capacity(seq.size_hint().unwrap_or_default());
+        while let Some(value) = seq.next_element()? {
+            values.push(value);
+        }
+        Ok(MultipartValue::List(values))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<MultipartValue, A::Error> {
+        Value::deserialize(MapAccessDeserializer::new(map)).map(MultipartValue::Structured)
+    }
+}
+
+/// Passes the content of a newtype on to `File`, which expects to read the newtype itself.
+struct NewtypeDeserializer<D>(D);
+
+impl<'de, D: Deserializer<'de>> Deserializer<'de> for NewtypeDeserializer<D> {
+    type Error = D::Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, D::Error> {
+        visitor.visit_newtype_struct(self.0)
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf
+        option unit unit_struct newtype_struct seq tuple tuple_struct map struct enum
+        identifier ignored_any
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Multipart, MultipartBuilder, MultipartConfig, MultipartError};
+    use actix_web::FromRequest;
+    use std::collections::HashMap;
+
+    #[allow(dead_code)]
+    #[derive(Deserialize)]
+    struct CatchAll {
+        title: String,
+        #[serde(flatten)]
+        extra: HashMap<String, MultipartValue>,
+    }
+
+    #[allow(dead_code)]
+    #[derive(Deserialize)]
+    struct Known {
+        title: String,
+    }
+
+    fn builder() -> MultipartBuilder {
+        MultipartBuilder::new()
+            .text("title", "A title")
+            .text("count", "12")
+            .text("tags[]", "a")
+            .text("tags[]", "b")
+            .file("attachment", "fn.txt", "text/plain", "test")
+    }
+
+    #[actix_rt::test]
+    async fn test_catch_all() {
+        let (req, mut payload) = builder().test_request().unwrap().to_http_parts();
+
+        match Multipart::<CatchAll>::from_request(&req, &mut payload).await {
+            Ok(data) => {
+                assert_eq!(data.title, "A title");
+                assert_eq!(data.extra.len(), 3);
+                assert_eq!(data.extra["count"].as_text(), Some("12"));
+                let tags = data.extra["tags"].as_list().unwrap();
+                assert_eq!(tags[1].as_text(), Some("b"));
+                assert_eq!(data.extra["attachment"].as_file().unwrap().data(), b"test");
+            }
+            Err(_) => panic!("Failed to parse multipart into structure"),
+        }
+    }
+
+    #[actix_rt::test]
+    async fn test_strict_mode() {
+        let config = MultipartConfig::default().strict(true);
+
+        let (req, mut payload) = builder()
+            .test_request()
+            .unwrap()
+            .app_data(config.clone())
+            .to_http_parts();
+        let err = Multipart::<Known>::from_request(&req, &mut payload)
+            .await
+            .err()
+            .unwrap();
+        match err.as_error::<MultipartError>() {
+            Some(MultipartError::UnknownFields { fields }) => {
+                assert_eq!(fields, &["attachment", "count", "tags"])
+            }
+            _ => panic!("Expected unknown fields"),
+        }
+
+        let (req, mut payload) = builder()
+            .test_request()
+            .unwrap()
+            .app_data(config)
+            .to_http_parts();
+        assert!(Multipart::<CatchAll>::from_request(&req, &mut payload)
+            .await
+            .is_ok());
+    }
+}